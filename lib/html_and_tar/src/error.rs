@@ -0,0 +1,67 @@
+use std::{fmt, io};
+
+/// Everything that can go wrong while interleaving HTML and tar.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum TarError {
+    /// The head of the document does not fit into the name of the first header.
+    HeadTooLong { len: usize, limit: usize },
+    /// The head of the document does not end with the `>` of a tag.
+    BadTerminator,
+    /// A name that can not be stored for both tar and HTML, see [`crate::is_valid_name`].
+    InvalidName { name: String },
+    /// A directory entry, with a name ending in `/`, that has data.
+    DirectoryWithData { name: String },
+    /// An entry is larger than the 11 octal digits of the size field can represent.
+    SizeOverflow { size: usize },
+    /// The first entry would end before the head of the document that it contains.
+    OffsetUnderflow { offset: usize, consumed: usize },
+    /// The document can not hold the entries.
+    InvalidDocument { reason: &'static str },
+    /// Writing the output failed.
+    Io(io::Error),
+}
+
+impl fmt::Display for TarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TarError::HeadTooLong { len, limit } => write!(
+                f,
+                "head of the document has {len} bytes, at most {limit} fit into a tar header"
+            ),
+            TarError::BadTerminator => write!(f, "head of the document does not end with `>`"),
+            TarError::InvalidName { name } => {
+                write!(f, "name {name:?} can not be stored in HTML")
+            }
+            TarError::DirectoryWithData { name } => {
+                write!(f, "directory `{name}` must not have data")
+            }
+            TarError::SizeOverflow { size } => write!(
+                f,
+                "entry of {size} bytes exceeds the limit of {} bytes of a tar header",
+                crate::TarHeader::MAX_SIZE
+            ),
+            TarError::OffsetUnderflow { offset, consumed } => write!(
+                f,
+                "entries at offset {offset} would start within the head of {consumed} bytes"
+            ),
+            TarError::InvalidDocument { reason } => write!(f, "invalid document: {reason}"),
+            TarError::Io(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for TarError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TarError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TarError {
+    fn from(err: io::Error) -> Self {
+        TarError::Io(err)
+    }
+}
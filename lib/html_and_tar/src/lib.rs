@@ -1,15 +1,39 @@
+//! Write an HTML document that is also a tar archive.
+//!
+//! The files are embedded as `<template>` elements, see [`HtmlTarWriter`]. The document itself is
+//! split around them into two more entries, which a listing shows besides the files:
+//!
+//! - [`HEAD_NAME`], the document from the end of the `<html>` tag up to the files. Its header
+//!   starts the document, the name that tar stores there is the tag itself and is replaced by an
+//!   extended header record.
+//! - [`TAIL_NAME`], the rest of the document after the files.
+//!
+//! Both are fragments of HTML. Extracting the archive writes these two besides the files.
 use base64::{engine::general_purpose::STANDARD, Engine as _};
+use std::io::Write;
+
+mod error;
+
+pub use error::TarError;
 
 mod bytemuck {
     pub fn bytes_of(tar: &super::TarHeader) -> &[u8] {
         let len = core::mem::size_of_val(tar);
-        unsafe { &*core::slice::from_raw_parts(tar as *const _ as *const u8, len) }
+        unsafe { core::slice::from_raw_parts(tar as *const _ as *const u8, len) }
     }
 }
 
+/// The name of the entry with the document up to the files.
+pub const HEAD_NAME: &str = ".head.html";
+
+/// The name of the entry with the document after the files.
+pub const TAIL_NAME: &str = ".html";
+
 #[derive(Default)]
 pub struct TarEngine {
     len: u64,
+    /// The data of the last entry was stored raw, in a comment that the next header closes.
+    comment_open: bool,
 }
 
 #[repr(C)]
@@ -35,17 +59,19 @@ pub struct TarHeader {
 }
 
 pub struct Entry<'la> {
-    /// An ascii name for this file.
+    /// The path of this file, ending in a `/` for a directory. See [`is_valid_name`].
     pub name: &'la str,
-    /// The data in its raw form. It will be re-encoded to be HTML safe.
+    /// The data in its raw form. It will be re-encoded to be HTML safe, unless stored raw.
     pub data: &'la [u8],
 }
 
 pub struct InitialEscape {
-    /// What Tar header describes the start of the HTML?
+    /// The extended header that starts the HTML, named by the start of the document.
     pub header: TarHeader,
     /// How much of the HTML did we consume?
     pub consumed: usize,
+    /// The records of the extended header, padded to a full block, followed by the file header.
+    /// They give the entry the name [`HEAD_NAME`], all within an attribute of the `<html>` tag.
     pub extra: Vec<u8>,
 }
 
@@ -53,10 +79,14 @@ pub struct EscapedData {
     pub padding: &'static [u8],
     /// The header entry, which transitions us into TAR semantics.
     pub header: TarHeader,
-    /// The file entry which closes the HTML tag with the file name visible to both tar as well as
-    /// HTML under appropriate attributes.
+    /// The records of the extended header, padded to a full block. They name the file for tar,
+    /// and for HTML as the `id` attribute.
+    pub extended: Vec<u8>,
+    /// The file entry which closes the HTML tag.
     pub file: TarHeader,
     pub data: Vec<u8>,
+    /// Whether the data is stored as is, instead of encoded as base64.
+    pub raw: bool,
 }
 
 pub struct EscapedSentinel {
@@ -65,149 +95,365 @@ pub struct EscapedSentinel {
     pub header: TarHeader,
 }
 
-impl TarEngine {
-    /// Mangle the HTML prefix such that we can interpret it as a tar header.
+pub struct EscapedTrailer {
+    /// Pads the final entry, opening a comment around the trailer when there is room for it.
+    pub padding: &'static [u8],
+    /// The two zero blocks that end the archive.
+    pub trailer: &'static [u8],
+    /// Closes the comment of the padding, tar readers stop before it at the trailer.
+    pub close: &'static [u8],
+}
+
+/// Writes an HTML document that is also a tar archive of some files.
+///
+/// The files are inserted as `<template>` elements after a marker in the document, each with the
+/// name of the file as its `id`. The content is the data encoded as base64, or with
+/// [`HtmlTarWriter::raw`] the data itself inside a comment.
+pub struct HtmlTarWriter<'h, W: Write> {
+    out: W,
+    html: &'h [u8],
+    marker: &'h [u8],
+    engine: TarEngine,
+    raw: bool,
+}
+
+impl<'h, W: Write> HtmlTarWriter<'h, W> {
+    /// Prepare to write the document, the files go directly after the first `marker`.
     ///
-    /// Must not modify HTML semantics.
-    pub fn start_of_file(&mut self, html_head: &[u8], entry_offset: usize) -> InitialEscape {
-        assert!(html_head.len() < 94);
-        assert_eq!(html_head.last().copied(), Some(b'>'));
+    /// The marker must end where elements are allowed, for instance at the end of a start tag such
+    /// as `<div id="files">`.
+    pub fn new(out: W, html: &'h [u8], marker: &'h [u8]) -> Self {
+        HtmlTarWriter {
+            out,
+            html,
+            marker,
+            engine: TarEngine::default(),
+            raw: false,
+        }
+    }
 
-        let consumed = html_head.len();
-        let all_except_close = html_head.len() - 1;
+    /// Store the data of entries as is, so that tar extracts the original files.
+    ///
+    /// The data is placed in a comment, which HTML does not parse. Entries which contain the end
+    /// of a comment are still encoded as base64.
+    pub fn raw(mut self, raw: bool) -> Self {
+        self.raw = raw;
+        self
+    }
 
-        let mut this = TarHeader::EMPTY;
-        this.name[1..][..all_except_close].copy_from_slice(&html_head[..all_except_close]);
-        this.name[1..][all_except_close..][..5].copy_from_slice(b"__A=\"");
-        this.prefix[153..].copy_from_slice(b"\">");
-        this.typeflag = b'0';
+    /// Write the whole document with all the entries, and return the writer.
+    pub fn write_entries<'la>(
+        mut self,
+        entries: impl IntoIterator<Item = Entry<'la>>,
+    ) -> Result<W, TarError> {
+        let html = self.html;
+        let head = Self::find(html, b"<html", 0)
+            .and_then(|start| Self::find(html, b">", start))
+            .map(|end| end + 1)
+            .ok_or(TarError::InvalidDocument {
+                reason: "no html tag in the document",
+            })?;
+
+        let insert = Self::find(html, self.marker, head)
+            .map(|start| start + self.marker.len())
+            .ok_or(TarError::InvalidDocument {
+                reason: "no marker in the document after the html tag",
+            })?;
+
+        let mut entries = entries.into_iter().peekable();
+
+        // Without any files, the whole document is the data of the first entry.
+        let with_entries = entries.peek().is_some();
+        let html_end = if with_entries { insert } else { html.len() };
+
+        let init = self.engine.start_of_file(&html[..head], html_end)?;
+        self.out.write_all(init.header.as_bytes())?;
+        self.out.write_all(&init.extra)?;
+        self.out.write_all(&html[init.consumed..html_end])?;
+
+        if with_entries {
+            let mut first = true;
+            for entry in entries {
+                let data = match (first, self.raw) {
+                    (true, false) => self.engine.escaped_insert_base64(entry),
+                    (false, false) => self.engine.escaped_continue_base64(entry),
+                    (true, true) => self.engine.escaped_insert_raw(entry),
+                    (false, true) => self.engine.escaped_continue_raw(entry),
+                }?;
+
+                first = false;
+                self.out.write_all(data.padding)?;
+                self.out.write_all(data.header.as_bytes())?;
+                self.out.write_all(&data.extended)?;
+                self.out.write_all(data.file.as_bytes())?;
+                self.out.write_all(&data.data)?;
+            }
+
+            let end = self.engine.escaped_end(html.len() - insert)?;
+            self.out.write_all(end.padding)?;
+            self.out.write_all(end.header.as_bytes())?;
+            self.out.write_all(&html[insert..])?;
+        }
 
-        let tail_len = entry_offset.checked_sub(consumed).unwrap();
-        this.assign_size(tail_len);
-        this.assign_standards();
-        this.assign_checksum();
+        let trailer = self.engine.insert_end();
+        self.out.write_all(trailer.padding)?;
+        self.out.write_all(trailer.trailer)?;
+        self.out.write_all(trailer.close)?;
 
-        self.len += core::mem::size_of::<TarHeader>() as u64;
-        self.len += tail_len as u64;
+        Ok(self.out)
+    }
 
-        InitialEscape {
-            header: this,
-            // extra refers to all the data we are adding. Which isn't anything yet.
-            extra: vec![],
-            consumed,
+    fn find(html: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+        if needle.is_empty() {
+            return None;
         }
+
+        html[from..]
+            .windows(needle.len())
+            .position(|window| window == needle)
+            .map(|pos| from + pos)
     }
+}
+
+impl TarEngine {
+    /// Mangle the HTML prefix such that we can interpret it as a tar header.
+    ///
+    /// Must not modify HTML semantics.
+    pub fn start_of_file(
+        &mut self,
+        html_head: &[u8],
+        entry_offset: usize,
+    ) -> Result<InitialEscape, TarError> {
+        // Room for the attribute that is added to the tag.
+        const LIMIT: usize = 93;
+
+        if html_head.len() > LIMIT {
+            return Err(TarError::HeadTooLong {
+                len: html_head.len(),
+                limit: LIMIT,
+            });
+        }
 
-    pub fn escaped_insert_base64(&mut self, Entry { name, data }: Entry) -> EscapedData {
-        assert!(name.is_ascii(), "Name must be ascii");
+        if html_head.last() != Some(&b'>') {
+            return Err(TarError::BadTerminator);
+        }
 
-        assert!(
-            name.chars().all(|c| c.is_ascii_alphanumeric()),
-            "Name {name} must be HTML compatible without escapes"
-        );
+        let consumed = html_head.len();
+        let all_except_close = html_head.len() - 1;
 
-        let padding = self.pad_to_fit();
-        let data = STANDARD.encode(data).into_bytes();
+        let tail_len = entry_offset
+            .checked_sub(consumed)
+            .ok_or(TarError::OffsetUnderflow {
+                offset: entry_offset,
+                consumed,
+            })?;
 
-        const START: &[u8] = b"\0<template __A=\"";
-        const DATA_START: &[u8] = b"\">";
-        const ID: &[u8] = b"\" id=\"";
-        const CONT: &[u8] = b"\" __B=\"";
+        // The name of the first header is the start of the document itself, with the HTML tag
+        // left open by an attribute that spans the following blocks. Tar reads it as an extended
+        // header, whose record replaces that name.
+        let mut extended = pax_record("path", HEAD_NAME);
 
         let mut this = TarHeader::EMPTY;
-        this.name[..START.len()].copy_from_slice(START);
-        this.assign_size(0);
+        this.assign_size(extended.len())?;
+        let mut file = TarHeader::EMPTY;
+        file.assign_size(tail_len)?;
+
+        this.name[..all_except_close].copy_from_slice(&html_head[..all_except_close]);
+        // The attribute must be separated from the tag name, or a previous attribute.
+        let attribute: &[u8] = match html_head[..all_except_close].last() {
+            Some(b' ' | b'\t' | b'\n') => b"__A=\"",
+            _ => b" __A=\"",
+        };
+        this.name[all_except_close..][..attribute.len()].copy_from_slice(attribute);
+        this.typeflag = b'x';
         this.assign_standards();
-        let end_start = this.prefix.len() - ID.len();
-        this.prefix[end_start..].copy_from_slice(ID);
         this.assign_checksum();
         self.len += core::mem::size_of::<TarHeader>() as u64;
-
-        let mut file = TarHeader::EMPTY;
-        let end_start = this.prefix.len() - DATA_START.len();
-        file.name[..name.len()].copy_from_slice(name.as_bytes());
-        file.name[name.len()..][1..][..CONT.len()].copy_from_slice(CONT);
-        file.assign_size(data.len());
-        file.prefix[end_start..].copy_from_slice(DATA_START);
+        self.len += extended.len() as u64;
+        extended.extend_from_slice(self.pad_to_fit());
+
+        // The file header closes the attribute and the tag, the rest of the HTML is its data.
+        file.name[..HEAD_NAME.len()].copy_from_slice(HEAD_NAME.as_bytes());
+        file.prefix[153..].copy_from_slice(b"\">");
+        file.typeflag = b'0';
+        file.assign_standards();
         file.assign_checksum();
+        extended.extend_from_slice(file.as_bytes());
         self.len += core::mem::size_of::<TarHeader>() as u64;
+        self.len += tail_len as u64;
 
-        // Followed by the data.
-        self.len += data.len() as u64;
-
-        EscapedData {
-            padding,
+        Ok(InitialEscape {
             header: this,
-            file,
-            data,
-        }
+            extra: extended,
+            consumed,
+        })
+    }
+
+    pub fn escaped_insert_base64(&mut self, entry: Entry) -> Result<EscapedData, TarError> {
+        self.escaped(false, entry, false)
     }
 
-    pub fn escaped_continue_base64(&mut self, Entry { name, data }: Entry) -> EscapedData {
-        assert!(name.is_ascii(), "Name must be ascii");
+    pub fn escaped_continue_base64(&mut self, entry: Entry) -> Result<EscapedData, TarError> {
+        self.escaped(true, entry, false)
+    }
+
+    /// Like `escaped_insert_base64`, but stores the data as is when HTML allows it.
+    ///
+    /// The data is then placed in a comment inside the template, so it must not contain the end of
+    /// a comment, see [`is_comment_safe`]. Otherwise it is still encoded as base64.
+    pub fn escaped_insert_raw(&mut self, entry: Entry) -> Result<EscapedData, TarError> {
+        self.escaped(false, entry, true)
+    }
 
-        assert!(
-            name.chars().all(|c| c.is_ascii_alphanumeric()),
-            "Name {name} must be HTML compatible without escapes"
-        );
+    /// Like `escaped_continue_base64`, but stores the data as is when HTML allows it.
+    pub fn escaped_continue_raw(&mut self, entry: Entry) -> Result<EscapedData, TarError> {
+        self.escaped(true, entry, true)
+    }
 
-        let padding = self.pad_to_fit();
-        let data = STANDARD.encode(data).into_bytes();
+    fn escaped(
+        &mut self,
+        continued: bool,
+        Entry { name, data }: Entry,
+        raw: bool,
+    ) -> Result<EscapedData, TarError> {
+        if !is_valid_name(name) {
+            return Err(TarError::InvalidName { name: name.into() });
+        }
 
-        const START: &[u8] = b"\0</template><template __A=\"";
-        const DATA_START: &[u8] = b"\">";
-        const ID: &[u8] = b"\" id=\"";
-        const CONT: &[u8] = b"\" __B=\"";
+        let directory = name.ends_with('/');
+        if directory && !data.is_empty() {
+            return Err(TarError::DirectoryWithData { name: name.into() });
+        }
 
+        let raw = raw && is_comment_safe(data);
+        let data = if raw {
+            data.to_vec()
+        } else {
+            STANDARD.encode(data).into_bytes()
+        };
+
+        // The extended header holds the full name for tar, still within the attribute. The
+        // comment record, which tar ignores, closes the attribute and gives HTML its `id`
+        // with the same name. The next attribute spans the file header.
+        let html = format!("html\" id=\"{}\" __B=\"", escape_attribute(name));
+        let mut extended = pax_record("path", name);
+        extended.extend_from_slice(&pax_record("comment", &html));
+
+        // Sizes first, the engine is left as it was if they do not fit.
         let mut this = TarHeader::EMPTY;
-        this.name[..START.len()].copy_from_slice(START);
-        this.assign_size(0);
+        this.assign_size(extended.len())?;
+        let mut file = TarHeader::EMPTY;
+        file.assign_size(data.len())?;
+
+        // A continued entry closes the template, and the comment of raw data, before it.
+        let start: &[u8] = match (continued, self.comment_open) {
+            (false, _) => b"\0<template __A=\"",
+            (true, false) => b"\0</template><template __A=\"",
+            (true, true) => b"\0--></template><template __A=\"",
+        };
+
+        let data_start: &[u8] = if raw { b"\"><!--" } else { b"\">" };
+        self.comment_open = raw;
+
+        let padding = self.pad_to_fit();
+        this.name[..start.len()].copy_from_slice(start);
+        this.typeflag = b'x';
         this.assign_standards();
-        let end_start = this.prefix.len() - ID.len();
-        this.prefix[end_start..].copy_from_slice(ID);
         this.assign_checksum();
         self.len += core::mem::size_of::<TarHeader>() as u64;
-
-        let mut file = TarHeader::EMPTY;
-        let end_start = file.prefix.len() - DATA_START.len();
-        file.name[..name.len()].copy_from_slice(name.as_bytes());
-        file.name[name.len()..][1..][..CONT.len()].copy_from_slice(CONT);
-        file.assign_size(data.len());
-        file.prefix[end_start..].copy_from_slice(DATA_START);
+        self.len += extended.len() as u64;
+        extended.extend_from_slice(self.pad_to_fit());
+
+        // Readers without support for extended headers get as much of the name as fits.
+        let short = &name.as_bytes()[..name.len().min(99)];
+        file.name[..short.len()].copy_from_slice(short);
+        file.typeflag = if directory { b'5' } else { b'0' };
+        file.assign_standards();
+        if directory {
+            file.mode.copy_from_slice(b"0000755\0");
+        }
+        // At the very end of the header, so the data follows the tag directly.
+        let end_start = file.__padding.len() - data_start.len();
+        file.__padding[end_start..].copy_from_slice(data_start);
         file.assign_checksum();
         self.len += core::mem::size_of::<TarHeader>() as u64;
 
         // Followed by the data.
         self.len += data.len() as u64;
 
-        EscapedData {
+        Ok(EscapedData {
             padding,
             header: this,
+            extended,
             file,
             data,
-        }
+            raw,
+        })
     }
 
     /// End a sequence of escaped data, with a particular skip of raw HTML bytes to follow until
     /// the next blocks of such data (again starting as `escaped_insert_base64`).
-    pub fn escaped_end(&mut self, skip: usize) -> EscapedSentinel {
+    pub fn escaped_end(&mut self, skip: usize) -> Result<EscapedSentinel, TarError> {
+        let mut this = TarHeader::EMPTY;
+        this.assign_size(skip)?;
+
         let padding = self.pad_to_fit();
-        const END: &[u8] = b"\0</template>";
+        // Still within the template, so the name is not shown.
+        const NAME: &[u8] = TAIL_NAME.as_bytes();
+        let end: &[u8] = if self.comment_open {
+            b"\0--></template>"
+        } else {
+            b"\0</template>"
+        };
 
-        let mut this = TarHeader::EMPTY;
-        this.assign_size(skip);
-        this.prefix[155 - END.len()..].copy_from_slice(END);
+        self.comment_open = false;
+
+        this.name[..NAME.len()].copy_from_slice(NAME);
+        this.typeflag = b'0';
+        this.prefix[155 - end.len()..].copy_from_slice(end);
         this.assign_standards();
         this.assign_checksum();
+        self.len += core::mem::size_of::<TarHeader>() as u64;
+
+        // Followed by the raw HTML.
+        self.len += skip as u64;
 
-        EscapedSentinel {
+        Ok(EscapedSentinel {
             padding,
             header: this,
-        }
+        })
     }
 
-    pub fn insert_end() -> TarHeader {
-        todo!()
+    /// End the archive after the raw HTML of the last `escaped_end`.
+    ///
+    /// The padding opens a comment that hides the trailer from HTML, if the final entry leaves
+    /// room for it, and the comment is closed after the trailer. Otherwise the trailer follows the
+    /// end of the document directly, where HTML parsers ignore its null bytes.
+    pub fn insert_end(&mut self) -> EscapedTrailer {
+        static COMMENTED_PADDING: [u8; 512] = {
+            let mut padding = [0; 512];
+            padding[0] = b'<';
+            padding[1] = b'!';
+            padding[2] = b'-';
+            padding[3] = b'-';
+            padding
+        };
+
+        static TRAILER: [u8; 1024] = [0; 1024];
+
+        let (padding, close): (_, &[u8]) = match self.pad_to_fit() {
+            padding if padding.len() >= 4 => (&COMMENTED_PADDING[..padding.len()], b"-->"),
+            padding => (padding, b""),
+        };
+
+        self.len += TRAILER.len() as u64;
+        self.len += close.len() as u64;
+
+        EscapedTrailer {
+            padding,
+            trailer: &TRAILER,
+            close,
+        }
     }
 
     fn pad_to_fit(&mut self) -> &'static [u8] {
@@ -218,6 +464,43 @@ impl TarEngine {
     }
 }
 
+/// Can the name be stored for both tar and HTML?
+///
+/// Any path is fine, but it must not be empty, contain control characters, or a double quote
+/// which would end the attribute that covers it. The name is also the `id` of its element, which
+/// must not contain whitespace, nor the brackets of tags.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name
+            .contains(|c: char| matches!(c, '"' | '<' | '>') || c.is_control() || c.is_whitespace())
+}
+
+/// Escape a valid name for a quoted attribute value.
+fn escape_attribute(name: &str) -> String {
+    name.replace('&', "&amp;")
+}
+
+/// An extended header record, `"%d %s=%s\n"` where the length includes itself.
+fn pax_record(key: &str, value: &str) -> Vec<u8> {
+    let rest = key.len() + value.len() + 3;
+    let mut len = rest + 1;
+    while len != rest + len.to_string().len() {
+        len = rest + len.to_string().len();
+    }
+
+    format!("{len} {key}={value}\n").into_bytes()
+}
+
+/// Can the data be placed in an HTML comment as is?
+///
+/// It must not end the comment early, and not start in a way that closes the opening `<!--`.
+pub fn is_comment_safe(data: &[u8]) -> bool {
+    !data.starts_with(b">")
+        && !data.starts_with(b"->")
+        && !data.windows(3).any(|window| window == b"-->")
+        && !data.windows(4).any(|window| window == b"--!>")
+}
+
 impl TarHeader {
     pub fn as_bytes(&self) -> &[u8] {
         bytemuck::bytes_of(self)
@@ -249,10 +532,18 @@ impl TarHeader {
         self.chksum.copy_from_slice(bytes.as_bytes());
     }
 
-    fn assign_size(&mut self, size: usize) {
+    /// The largest size in the 11 octal digits of the field.
+    const MAX_SIZE: u64 = 0o77777777777;
+
+    fn assign_size(&mut self, size: usize) -> Result<(), TarError> {
+        if size as u64 > Self::MAX_SIZE {
+            return Err(TarError::SizeOverflow { size });
+        }
+
         let bytes = format!("{size:011o}\0");
         // Note: this is numeric, so can not contain a closing quote.
         self.size.copy_from_slice(bytes.as_bytes());
+        Ok(())
     }
 
     const EMPTY: Self = TarHeader {
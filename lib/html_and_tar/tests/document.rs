@@ -0,0 +1,218 @@
+use html_and_tar::{Entry, HtmlTarWriter, TarError};
+use std::io::Read;
+
+const HTML: &[u8] =
+    b"<!DOCTYPE html><html lang=\"en\"><body><div id=\"files\"></div></body></html>";
+const MARKER: &[u8] = b"<div id=\"files\">";
+
+fn write(entries: Vec<Entry>, raw: bool) -> Vec<u8> {
+    HtmlTarWriter::new(vec![], HTML, MARKER)
+        .raw(raw)
+        .write_entries(entries)
+        .unwrap()
+}
+
+/// All entries of the document read as a tar archive, by name and data.
+fn extract(document: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let mut archive = tar::Archive::new(document);
+    archive
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            let mut entry = entry.unwrap();
+            let name = entry.path().unwrap().to_str().unwrap().to_owned();
+            let mut data = vec![];
+            entry.read_to_end(&mut data).unwrap();
+            (name, data)
+        })
+        .collect()
+}
+
+#[test]
+fn trailer_is_commented() {
+    let document = write(
+        vec![Entry {
+            name: "hello.txt",
+            data: b"Hello, world!",
+        }],
+        false,
+    );
+
+    let end = document
+        .windows(7)
+        .rposition(|window| window == b"</html>")
+        .unwrap()
+        + 7;
+
+    // A comment only of null bytes, including the two zero blocks of the trailer.
+    let tail = &document[end..];
+    assert!(tail.starts_with(b"<!--"), "{tail:?}");
+    assert!(tail.ends_with(b"-->"), "{tail:?}");
+    assert!(tail[4..tail.len() - 3].iter().all(|&b| b == 0));
+    assert!(tail.len() - 7 >= 1024);
+
+    let names = extract(&document)
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    assert_eq!(names.len(), 3);
+    assert_eq!(names[0], ".head.html");
+    assert_eq!(names[1], "hello.txt");
+    assert_eq!(names[2], ".html");
+}
+
+#[test]
+fn marker_at_the_end() {
+    let html = b"<html><p>";
+    let document = HtmlTarWriter::new(vec![], html, b"<p>")
+        .write_entries([Entry {
+            name: "last.txt",
+            data: b"after everything",
+        }])
+        .unwrap();
+
+    let entries = extract(&document);
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[1].0, "last.txt");
+    assert_eq!(entries[1].1, b"YWZ0ZXIgZXZlcnl0aGluZw==");
+    // The rest of the document is empty.
+    assert_eq!(entries[2], (".html".to_owned(), vec![]));
+}
+
+#[test]
+fn without_entries() {
+    let document = write(vec![], false);
+    let entries = extract(&document);
+
+    // The whole document after the head, which starts the first header.
+    let head = b"<!DOCTYPE html><html lang=\"en\">".len();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].0, ".head.html");
+    assert_eq!(entries[0].1, &HTML[head..]);
+}
+
+#[test]
+fn reports_invalid_names() {
+    let result = HtmlTarWriter::new(vec![], b"<html><p>", b"<p>").write_entries([Entry {
+        name: "",
+        data: b"",
+    }]);
+
+    assert!(matches!(result, Err(TarError::InvalidName { name }) if name.is_empty()));
+}
+
+#[test]
+fn raw_entries_extract_as_is() {
+    let binary = (0..=255).collect::<Vec<u8>>();
+    let document = write(
+        vec![
+            Entry {
+                name: "binary.bin",
+                data: &binary,
+            },
+            Entry {
+                name: "page.html",
+                data: b"<script>alert(1)</script>",
+            },
+            Entry {
+                name: "empty",
+                data: b"",
+            },
+        ],
+        true,
+    );
+
+    let entries = extract(&document);
+    assert_eq!(entries.len(), 5);
+    assert_eq!(entries[1], ("binary.bin".to_owned(), binary));
+    assert_eq!(entries[2].1, b"<script>alert(1)</script>");
+    assert_eq!(entries[3].1, b"");
+
+    // Inside a comment, where HTML does not see the tag.
+    let script = b"<!--<script>alert(1)</script>\0";
+    assert!(document
+        .windows(script.len())
+        .any(|window| window == script));
+}
+
+#[test]
+fn raw_falls_back_to_base64() {
+    let unsafe_data: [&[u8]; 4] = [b"a --> b", b"a --!> b", b">a", b"->a"];
+    let entries = unsafe_data
+        .iter()
+        .map(|data| Entry { name: "file", data })
+        .collect();
+
+    let document = write(entries, true);
+    let extracted = extract(&document);
+
+    for (data, (_, extracted)) in unsafe_data.iter().zip(&extracted[1..]) {
+        assert!(!html_and_tar::is_comment_safe(data));
+        assert_eq!(extracted, base64(data).as_bytes());
+    }
+}
+
+fn base64(data: &[u8]) -> String {
+    use base64::Engine as _;
+    base64::engine::general_purpose::STANDARD.encode(data)
+}
+
+#[test]
+fn long_paths_and_directories() {
+    let long = format!("{}model&scene.gltf", "nested/".repeat(20));
+    let document = write(
+        vec![
+            Entry {
+                name: "assets/",
+                data: b"",
+            },
+            Entry {
+                name: &long,
+                data: b"{}",
+            },
+        ],
+        true,
+    );
+
+    let mut archive = tar::Archive::new(&document[..]);
+    let headers = archive
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            let path = entry.path().unwrap().to_str().unwrap().to_owned();
+            (path, entry.header().entry_type())
+        })
+        .collect::<Vec<_>>();
+
+    assert!(long.len() > 100);
+    assert_eq!(
+        headers[1],
+        ("assets/".to_owned(), tar::EntryType::Directory)
+    );
+    assert_eq!(headers[2], (long.clone(), tar::EntryType::Regular));
+
+    // And the same name for HTML, escaped in the attribute.
+    let id = format!("id=\"{}\"", long.replace('&', "&amp;"));
+    assert!(document
+        .windows(id.len())
+        .any(|window| window == id.as_bytes()));
+}
+
+#[test]
+fn invalid_names() {
+    for name in ["", "a b", "a\tb", "a\nb", "a\"b", "a<b", "a>b", "a\0b"] {
+        assert!(!html_and_tar::is_valid_name(name), "{name:?}");
+
+        let result =
+            HtmlTarWriter::new(vec![], HTML, MARKER).write_entries([Entry { name, data: b"" }]);
+        assert!(
+            matches!(&result, Err(TarError::InvalidName { name: invalid }) if invalid == name),
+            "{name:?}"
+        );
+    }
+
+    for name in ["a", "assets/model.gltf", "scene-1.png", "dir/", "a&b", "ü"] {
+        assert!(html_and_tar::is_valid_name(name), "{name:?}");
+    }
+}
@@ -0,0 +1,81 @@
+use html_and_tar::{Entry, HtmlTarWriter, TarEngine, TarError};
+
+fn write(html: &[u8], marker: &[u8], entries: Vec<Entry>) -> Result<Vec<u8>, TarError> {
+    HtmlTarWriter::new(vec![], html, marker).write_entries(entries)
+}
+
+#[test]
+fn head_too_long() {
+    let html = format!("<!DOCTYPE html><html lang=\"{}\"><p>", "x".repeat(80));
+    let result = write(html.as_bytes(), b"<p>", vec![]);
+
+    // Everything up to the `<p>` is the head.
+    let head = html.len() - 3;
+    assert!(matches!(
+        result,
+        Err(TarError::HeadTooLong { len, limit: 93 }) if len == head
+    ));
+}
+
+#[test]
+fn bad_terminator() {
+    let result = TarEngine::default().start_of_file(b"<html", 5);
+    assert!(matches!(result, Err(TarError::BadTerminator)));
+}
+
+#[test]
+fn offset_underflow() {
+    let result = TarEngine::default().start_of_file(b"<html>", 2);
+    assert!(matches!(
+        result,
+        Err(TarError::OffsetUnderflow {
+            offset: 2,
+            consumed: 6
+        })
+    ));
+}
+
+#[test]
+fn invalid_name() {
+    let mut engine = TarEngine::default();
+    let result = engine.escaped_insert_base64(Entry {
+        name: "no spaces",
+        data: b"",
+    });
+
+    assert!(matches!(result, Err(TarError::InvalidName { name }) if name == "no spaces"));
+}
+
+#[test]
+fn directory_with_data() {
+    let result = write(
+        b"<html><p>",
+        b"<p>",
+        vec![Entry {
+            name: "dir/",
+            data: b"data",
+        }],
+    );
+
+    assert!(matches!(result, Err(TarError::DirectoryWithData { name }) if name == "dir/"));
+}
+
+#[test]
+fn size_overflow() {
+    let mut engine = TarEngine::default();
+    let size = 0o77777777777 + 1;
+    let result = engine.escaped_end(size);
+
+    assert!(matches!(result, Err(TarError::SizeOverflow { size: s }) if s == size));
+    // The largest size still fits.
+    assert!(engine.escaped_end(size - 1).is_ok());
+}
+
+#[test]
+fn invalid_document() {
+    let no_html = write(b"<body><p>", b"<p>", vec![]);
+    let no_marker = write(b"<p><html>", b"<p>", vec![]);
+
+    assert!(matches!(no_html, Err(TarError::InvalidDocument { .. })));
+    assert!(matches!(no_marker, Err(TarError::InvalidDocument { .. })));
+}
@@ -0,0 +1,445 @@
+//! Rewriting of the trailing zip, such that the whole polyglot is a valid archive.
+//!
+//! A zip archive refers to its entries by offsets from the start of the file. The trailing zip
+//! section is placed after the whole module though, so all its offsets must be moved by the
+//! position of the section's data. We only rewrite the central directory and the end records,
+//! the local headers and file data are kept as they are.
+use crate::PackError;
+use std::ops::Range;
+
+const LOCAL_SIGNATURE: u32 = 0x04034b50;
+const CENTRAL_SIGNATURE: u32 = 0x02014b50;
+const EOCD_SIGNATURE: u32 = 0x06054b50;
+const EOCD64_SIGNATURE: u32 = 0x06064b50;
+const LOCATOR64_SIGNATURE: u32 = 0x07064b50;
+
+const LOCAL_LEN: usize = 30;
+const CENTRAL_LEN: usize = 46;
+const EOCD_LEN: usize = 22;
+const EOCD64_LEN: usize = 56;
+const LOCATOR64_LEN: usize = 20;
+
+/// The extra field holding 64-bit sizes and offsets.
+const ZIP64_EXTRA: u16 = 0x0001;
+
+/// Does the file end with an archive placed at `start`, as [`relocate`] leaves the trailing zip?
+///
+/// The central directory must lie after `start`, where the offsets of the archive say it is.
+/// Data that only starts like a zip does not have such end records.
+pub(crate) fn ends_with_zip(file: &[u8], start: usize) -> bool {
+    EndRecords::find(file)
+        .is_ok_and(|end| end.cd_start >= start as u64 && end.cd_offset == end.cd_start)
+}
+
+/// Move all offsets in a zip by the position it will be placed at.
+///
+/// Where an offset no longer fits its 32-bit field it is moved to the zip64 extra field, and
+/// zip64 end records are emitted.
+pub(crate) fn relocate(zip: &[u8], position: u64) -> Result<Vec<u8>, PackError> {
+    let end = EndRecords::find(zip)?;
+
+    // Where the archive actually starts, relative to where its offsets were counted from. This
+    // accounts for data in front of the archive as well as a previous relocation.
+    let shift = (end.cd_start + position) as i64 - end.cd_offset as i64;
+
+    let mut directory = vec![];
+    let mut needs_zip64 = end.is_zip64;
+
+    for entry in central_entries(zip, &end)? {
+        let mut header = zip[entry.header.clone()].to_vec();
+        let extra = relocate_entry(&mut header, &zip[entry.extra.clone()], shift)?;
+        needs_zip64 |= read_u32(&header, 42) == u32::MAX;
+
+        directory.extend_from_slice(&header);
+        directory.extend_from_slice(&zip[entry.name]);
+        directory.extend_from_slice(&extra);
+        directory.extend_from_slice(&zip[entry.comment]);
+    }
+
+    let cd_offset = end.cd_start + position;
+    let cd_size = directory.len() as u64;
+    needs_zip64 |= cd_offset >= u64::from(u32::MAX)
+        || cd_size >= u64::from(u32::MAX)
+        || end.entries >= u64::from(u16::MAX);
+
+    let mut out = zip[..end.cd_start as usize].to_vec();
+    out.extend_from_slice(&directory);
+
+    if needs_zip64 {
+        let eocd64_offset = out.len() as u64 + position;
+
+        push_u32(&mut out, EOCD64_SIGNATURE);
+        push_u64(&mut out, (EOCD64_LEN - 12) as u64);
+        push_u16(&mut out, 45);
+        push_u16(&mut out, 45);
+        push_u32(&mut out, 0);
+        push_u32(&mut out, 0);
+        push_u64(&mut out, end.entries);
+        push_u64(&mut out, end.entries);
+        push_u64(&mut out, cd_size);
+        push_u64(&mut out, cd_offset);
+
+        push_u32(&mut out, LOCATOR64_SIGNATURE);
+        push_u32(&mut out, 0);
+        push_u64(&mut out, eocd64_offset);
+        push_u32(&mut out, 1);
+    }
+
+    let entries = u16::try_from(end.entries).unwrap_or(u16::MAX);
+    push_u32(&mut out, EOCD_SIGNATURE);
+    push_u16(&mut out, 0);
+    push_u16(&mut out, 0);
+    push_u16(&mut out, entries);
+    push_u16(&mut out, entries);
+    push_u32(&mut out, saturate(cd_size));
+    push_u32(&mut out, saturate(cd_offset));
+    push_u16(&mut out, end.comment.len() as u16);
+    out.extend_from_slice(end.comment);
+
+    Ok(out)
+}
+
+/// Overwrite all timestamps of the entries, in the central directory and local headers.
+///
+/// This does not change the length of any record so all offsets stay valid. Besides the MS-DOS
+/// time of the headers, this also covers the timestamps of the extended timestamp and NTFS extra
+/// fields, and clears owner ids of unix extra fields.
+pub(crate) fn normalize_times(
+    zip: &mut [u8],
+    unix_time: u32,
+    dos: zip::DateTime,
+) -> Result<(), PackError> {
+    let end = EndRecords::find(zip)?;
+    let shift = end.cd_start as i64 - end.cd_offset as i64;
+
+    for entry in central_entries(zip, &end)? {
+        let local = local_offset(&zip[entry.header.clone()], &zip[entry.extra.clone()])?
+            .checked_add_signed(shift)
+            .map(|offset| offset as usize)
+            .filter(|&offset| offset + LOCAL_LEN <= zip.len())
+            .filter(|&offset| read_u32(zip, offset) == LOCAL_SIGNATURE)
+            .ok_or_else(|| invalid("local header not found"))?;
+
+        let header = entry.header.start;
+        zip[header + 12..header + 14].copy_from_slice(&dos.timepart().to_le_bytes());
+        zip[header + 14..header + 16].copy_from_slice(&dos.datepart().to_le_bytes());
+        normalize_extra(&mut zip[entry.extra], unix_time);
+
+        zip[local + 10..local + 12].copy_from_slice(&dos.timepart().to_le_bytes());
+        zip[local + 12..local + 14].copy_from_slice(&dos.datepart().to_le_bytes());
+
+        let name_len = usize::from(read_u16(zip, local + 26));
+        let extra_len = usize::from(read_u16(zip, local + 28));
+        let extra_start = local + LOCAL_LEN + name_len;
+        let extra = zip
+            .get_mut(extra_start..extra_start + extra_len)
+            .ok_or_else(|| invalid("local header is truncated"))?;
+        normalize_extra(extra, unix_time);
+    }
+
+    Ok(())
+}
+
+fn normalize_extra(extra: &mut [u8], unix_time: u32) {
+    // Windows file times count 100ns intervals since 1601.
+    let file_time = (u64::from(unix_time) + 11_644_473_600) * 10_000_000;
+    let mut at = 0;
+
+    while at + 4 <= extra.len() {
+        let id = read_u16(extra, at);
+        let len = usize::from(read_u16(extra, at + 2));
+        let Some(data) = extra.get_mut(at + 4..at + 4 + len) else {
+            return;
+        };
+
+        match id {
+            // Extended timestamp: flags, then up to three unix times.
+            0x5455 => {
+                for time in data[1.min(len)..].chunks_exact_mut(4) {
+                    time.copy_from_slice(&unix_time.to_le_bytes());
+                }
+            }
+            // NTFS: reserved, then tagged attributes of which tag 1 holds three file times.
+            0x000a => {
+                let mut attr = 4;
+                while attr + 4 <= data.len() {
+                    let tag = read_u16(data, attr);
+                    let size = usize::from(read_u16(data, attr + 2));
+                    if tag == 1 && size == 24 && attr + 4 + size <= data.len() {
+                        for time in data[attr + 4..attr + 4 + size].chunks_exact_mut(8) {
+                            time.copy_from_slice(&file_time.to_le_bytes());
+                        }
+                    }
+                    attr += 4 + size;
+                }
+            }
+            // Info-ZIP unix, old: access and modification time, then optional ids.
+            0x5855 => {
+                for (idx, chunk) in data.chunks_exact_mut(4).enumerate() {
+                    match idx {
+                        0 | 1 => chunk.copy_from_slice(&unix_time.to_le_bytes()),
+                        _ => chunk.fill(0),
+                    }
+                }
+            }
+            // Info-ZIP unix, new: version, then sized uid and gid.
+            0x7875 => {
+                let mut idx = 1;
+                while idx < data.len() {
+                    let end = (idx + 1 + usize::from(data[idx])).min(data.len());
+                    data[idx + 1..end].fill(0);
+                    idx = end;
+                }
+            }
+            _ => {}
+        }
+
+        at += 4 + len;
+    }
+}
+
+/// Move the local header offset of one central directory entry, returning its new extra field.
+fn relocate_entry(header: &mut [u8], extra: &[u8], shift: i64) -> Result<Vec<u8>, PackError> {
+    let size_in_extra = read_u32(header, 24) == u32::MAX;
+    let compressed_in_extra = read_u32(header, 20) == u32::MAX;
+    let offset_in_extra = read_u32(header, 42) == u32::MAX;
+
+    // The zip64 fields are only present for those marked in the header, in this order.
+    let offset_pos = 8 * (usize::from(size_in_extra) + usize::from(compressed_in_extra));
+
+    let mut fields = vec![];
+    let mut at = 0;
+    let mut zip64 = None;
+
+    while at + 4 <= extra.len() {
+        let id = read_u16(extra, at);
+        let len = usize::from(read_u16(extra, at + 2));
+        let data = extra
+            .get(at + 4..at + 4 + len)
+            .ok_or_else(|| invalid("extra field is truncated"))?;
+
+        if id == ZIP64_EXTRA {
+            zip64 = Some(data.to_vec());
+        } else {
+            fields.push((id, data));
+        }
+
+        at += 4 + len;
+    }
+
+    let old_offset = local_offset(header, extra)?;
+    let new_offset = old_offset
+        .checked_add_signed(shift)
+        .ok_or_else(|| invalid("local header offset is out of range"))?;
+
+    if !offset_in_extra && new_offset < u64::from(u32::MAX) {
+        header[42..46].copy_from_slice(&(new_offset as u32).to_le_bytes());
+        return Ok(extra.to_vec());
+    }
+
+    let mut zip64 = zip64.unwrap_or_default();
+    if offset_in_extra {
+        if zip64.len() < offset_pos + 8 {
+            return Err(invalid("zip64 extra field lacks the local header offset"));
+        }
+
+        zip64[offset_pos..offset_pos + 8].copy_from_slice(&new_offset.to_le_bytes());
+    } else {
+        if zip64.len() < offset_pos {
+            return Err(invalid("zip64 extra field lacks the entry sizes"));
+        }
+
+        zip64.splice(offset_pos..offset_pos, new_offset.to_le_bytes());
+        header[42..46].copy_from_slice(&u32::MAX.to_le_bytes());
+    }
+
+    let mut extra = vec![];
+    push_u16(&mut extra, ZIP64_EXTRA);
+    push_u16(&mut extra, zip64.len() as u16);
+    extra.extend_from_slice(&zip64);
+
+    for (id, data) in fields {
+        push_u16(&mut extra, id);
+        push_u16(&mut extra, data.len() as u16);
+        extra.extend_from_slice(data);
+    }
+
+    if extra.len() > usize::from(u16::MAX) {
+        return Err(invalid("extra field grows too large"));
+    }
+
+    header[30..32].copy_from_slice(&(extra.len() as u16).to_le_bytes());
+    Ok(extra)
+}
+
+/// The offset of the local header, as recorded in a central directory entry.
+fn local_offset(header: &[u8], extra: &[u8]) -> Result<u64, PackError> {
+    let offset = read_u32(header, 42);
+    if offset != u32::MAX {
+        return Ok(u64::from(offset));
+    }
+
+    // The zip64 fields are only present for those marked in the header, in this order.
+    let size_in_extra = read_u32(header, 24) == u32::MAX;
+    let compressed_in_extra = read_u32(header, 20) == u32::MAX;
+    let offset_pos = 8 * (usize::from(size_in_extra) + usize::from(compressed_in_extra));
+
+    let mut at = 0;
+    while at + 4 <= extra.len() {
+        let id = read_u16(extra, at);
+        let len = usize::from(read_u16(extra, at + 2));
+
+        if id == ZIP64_EXTRA && offset_pos + 8 <= len && at + 4 + len <= extra.len() {
+            return Ok(read_u64(extra, at + 4 + offset_pos));
+        }
+
+        at += 4 + len;
+    }
+
+    Err(invalid("zip64 extra field lacks the local header offset"))
+}
+
+/// The parts of one entry in the central directory, as ranges of the archive.
+struct CentralEntry {
+    header: Range<usize>,
+    name: Range<usize>,
+    extra: Range<usize>,
+    comment: Range<usize>,
+}
+
+fn central_entries(zip: &[u8], end: &EndRecords) -> Result<Vec<CentralEntry>, PackError> {
+    let mut entries = vec![];
+    let mut at = end.cd_start as usize;
+
+    for _ in 0..end.entries {
+        let header = zip
+            .get(at..at + CENTRAL_LEN)
+            .filter(|header| read_u32(header, 0) == CENTRAL_SIGNATURE)
+            .ok_or_else(|| invalid("central directory entry not found"))?;
+
+        let name = at + CENTRAL_LEN..at + CENTRAL_LEN + usize::from(read_u16(header, 28));
+        let extra = name.end..name.end + usize::from(read_u16(header, 30));
+        let comment = extra.end..extra.end + usize::from(read_u16(header, 32));
+
+        if comment.end > zip.len() {
+            return Err(invalid("central directory entry is truncated"));
+        }
+
+        entries.push(CentralEntry {
+            header: at..at + CENTRAL_LEN,
+            name,
+            extra,
+            comment: comment.clone(),
+        });
+
+        at = comment.end;
+    }
+
+    Ok(entries)
+}
+
+/// The position of the central directory, as described by the end of the archive.
+struct EndRecords<'z> {
+    /// Where we found the central directory.
+    cd_start: u64,
+    /// Where the archive claims the central directory is.
+    cd_offset: u64,
+    entries: u64,
+    comment: &'z [u8],
+    is_zip64: bool,
+}
+
+impl<'z> EndRecords<'z> {
+    fn find(zip: &'z [u8]) -> Result<Self, PackError> {
+        if zip.len() < EOCD_LEN {
+            return Err(invalid(
+                "archive is shorter than an end of central directory record",
+            ));
+        }
+
+        // The record is followed by a comment of at most 64kB, which must end the file.
+        let search_start = zip.len().saturating_sub(EOCD_LEN + usize::from(u16::MAX));
+        let eocd = (search_start..=zip.len().saturating_sub(EOCD_LEN))
+            .rev()
+            .find(|&at| {
+                read_u32(zip, at) == EOCD_SIGNATURE
+                    && at + EOCD_LEN + usize::from(read_u16(zip, at + 20)) == zip.len()
+            })
+            .ok_or_else(|| invalid("no end of central directory record"))?;
+
+        let comment = &zip[eocd + EOCD_LEN..];
+        let locator = eocd
+            .checked_sub(LOCATOR64_LEN)
+            .filter(|&at| read_u32(zip, at) == LOCATOR64_SIGNATURE);
+
+        let Some(locator) = locator else {
+            let cd_size = u64::from(read_u32(zip, eocd + 12));
+            let cd_start = (eocd as u64)
+                .checked_sub(cd_size)
+                .ok_or_else(|| invalid("central directory size exceeds the archive"))?;
+
+            return Ok(EndRecords {
+                cd_start,
+                cd_offset: u64::from(read_u32(zip, eocd + 16)),
+                entries: u64::from(read_u16(zip, eocd + 10)),
+                comment,
+                is_zip64: false,
+            });
+        };
+
+        // The zip64 record is of variable size but we only need its fixed part. It may have been
+        // moved along with prefixed data, so search for it backwards from the locator.
+        let eocd64 = (0..=locator.saturating_sub(EOCD64_LEN))
+            .rev()
+            .filter(|&at| at + EOCD64_LEN <= locator)
+            .find(|&at| read_u32(zip, at) == EOCD64_SIGNATURE)
+            .ok_or_else(|| invalid("zip64 end of central directory record not found"))?;
+
+        let cd_size = read_u64(zip, eocd64 + 40);
+        let cd_start = (eocd64 as u64)
+            .checked_sub(cd_size)
+            .ok_or_else(|| invalid("central directory size exceeds the archive"))?;
+
+        Ok(EndRecords {
+            cd_start,
+            cd_offset: read_u64(zip, eocd64 + 48),
+            entries: read_u64(zip, eocd64 + 32),
+            comment,
+            is_zip64: true,
+        })
+    }
+}
+
+fn saturate(value: u64) -> u32 {
+    u32::try_from(value).unwrap_or(u32::MAX)
+}
+
+fn read_u16(data: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes(data[at..at + 2].try_into().unwrap())
+}
+
+fn read_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(data[at..at + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(data[at..at + 8].try_into().unwrap())
+}
+
+fn push_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn invalid(reason: &str) -> PackError {
+    PackError::InvalidZip {
+        reason: reason.to_owned(),
+    }
+}
@@ -0,0 +1,256 @@
+//! Bundling a directory into the zip of the `wah_polyglot_stage2_data` section.
+use crate::PackError;
+use std::{
+    io::{Cursor, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// A directory to be packed as the trailing zip.
+///
+/// Entries are sorted by their path, so the archive does not depend on the order in which the
+/// file system lists them. Symbolic links to files are stored as the file, links to directories
+/// are skipped.
+#[derive(Clone, Debug)]
+pub struct DataDir {
+    root: PathBuf,
+    compression: Compression,
+    exclude: Vec<glob::Pattern>,
+    skip: Vec<PathBuf>,
+    source_date: Option<u64>,
+}
+
+/// How to store files in the zip.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    Stored,
+    #[default]
+    Deflate,
+}
+
+impl DataDir {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        DataDir {
+            root: root.into(),
+            compression: Compression::default(),
+            exclude: vec![],
+            skip: vec![],
+            source_date: None,
+        }
+    }
+
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Record this time for all entries, in seconds since the unix epoch, instead of the
+    /// modification time of the files.
+    pub fn source_date(mut self, source_date: u64) -> Self {
+        self.source_date = Some(source_date);
+        self
+    }
+
+    /// Skip all files and directories whose path matches the glob.
+    ///
+    /// The pattern is matched against the path relative to the root, with `/` as separators. A
+    /// `*` also matches across separators, so `*.psd` skips such files in all directories.
+    pub fn exclude(mut self, pattern: &str) -> Result<Self, PackError> {
+        let pattern = glob::Pattern::new(pattern).map_err(|err| PackError::InvalidPattern {
+            pattern: pattern.to_owned(),
+            reason: err.msg.to_owned(),
+        })?;
+
+        self.exclude.push(pattern);
+        Ok(self)
+    }
+
+    /// Skip this file if it is found in the directory, such as an output written into it.
+    ///
+    /// The file does not need to exist, but its parent directory does by the time of the walk.
+    pub fn skip(mut self, path: impl Into<PathBuf>) -> Self {
+        self.skip.push(path.into());
+        self
+    }
+
+    /// Walk the directory and create the zip archive.
+    pub fn to_zip(&self) -> Result<Vec<u8>, PackError> {
+        let skip: Vec<_> = self
+            .skip
+            .iter()
+            .filter_map(|path| canonical(path))
+            .collect();
+        let mut entries = vec![];
+        self.walk(&self.root, "", &skip, &mut entries)?;
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let method = match self.compression {
+            Compression::Stored => zip::CompressionMethod::Stored,
+            Compression::Deflate => zip::CompressionMethod::Deflated,
+        };
+
+        let mut writer = zip::ZipWriter::new(Cursor::new(vec![]));
+        for entry in &entries {
+            let options = zip::write::FileOptions::default()
+                .compression_method(method)
+                .last_modified_time(entry.modified);
+
+            match &entry.file {
+                None => writer
+                    .add_directory(entry.name.as_str(), options.unix_permissions(0o755))
+                    .map_err(zip_err)?,
+                Some(path) => {
+                    let data = std::fs::read(path).map_err(|err| PackError::file(path, err))?;
+                    writer
+                        .start_file(entry.name.as_str(), options.unix_permissions(0o644))
+                        .map_err(zip_err)?;
+                    writer.write_all(&data)?;
+                }
+            }
+        }
+
+        Ok(writer.finish().map_err(zip_err)?.into_inner())
+    }
+
+    fn walk(
+        &self,
+        dir: &Path,
+        prefix: &str,
+        skip: &[PathBuf],
+        entries: &mut Vec<DirEntry>,
+    ) -> Result<(), PackError> {
+        let read_dir = std::fs::read_dir(dir).map_err(|err| PackError::file(dir, err))?;
+        let canonical_dir = std::fs::canonicalize(dir).map_err(|err| PackError::file(dir, err))?;
+
+        for item in read_dir {
+            let item = item.map_err(|err| PackError::file(dir, err))?;
+            let path = item.path();
+
+            let Some(name) = item
+                .file_name()
+                .to_str()
+                .map(|name| format!("{prefix}{name}"))
+            else {
+                return Err(PackError::InvalidPath { path });
+            };
+
+            if self.exclude.iter().any(|pattern| pattern.matches(&name)) {
+                continue;
+            }
+
+            if skip.contains(&canonical_dir.join(item.file_name())) {
+                continue;
+            }
+
+            let mut meta =
+                std::fs::symlink_metadata(&path).map_err(|err| PackError::file(&path, err))?;
+
+            // Links to directories may form a loop, only those to files are followed.
+            if meta.is_symlink() {
+                match std::fs::metadata(&path) {
+                    Ok(target) if target.is_file() => meta = target,
+                    _ => continue,
+                }
+            }
+
+            let modified = match self.source_date {
+                Some(secs) => zip_time(UNIX_EPOCH + Duration::from_secs(secs)),
+                None => meta.modified().map(zip_time).unwrap_or_default(),
+            };
+
+            if meta.is_dir() {
+                let name = format!("{name}/");
+                self.walk(&path, &name, skip, entries)?;
+                entries.push(DirEntry {
+                    name,
+                    file: None,
+                    modified,
+                });
+            } else {
+                entries.push(DirEntry {
+                    name,
+                    file: Some(path),
+                    modified,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The path with its directory resolved, for a file that may not exist yet.
+fn canonical(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    Some(std::fs::canonicalize(dir).ok()?.join(name))
+}
+
+struct DirEntry {
+    /// The name in the archive, directories end in `/`.
+    name: String,
+    file: Option<PathBuf>,
+    modified: zip::DateTime,
+}
+
+/// Convert to the MS-DOS time of zip entries, in UTC. Clamped to the representable range.
+pub(crate) fn zip_time(time: SystemTime) -> zip::DateTime {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs(),
+        Err(_) => 0,
+    };
+
+    let (days, secs) = (secs / 86400, secs % 86400);
+    let (year, month, day) = civil_from_days(days);
+    let year = year.clamp(1980, 2107) as u16;
+
+    zip::DateTime::from_date_and_time(
+        year,
+        month,
+        day,
+        (secs / 3600) as u8,
+        (secs / 60 % 60) as u8,
+        (secs % 60) as u8,
+    )
+    .unwrap_or_default()
+}
+
+/// The proleptic Gregorian date of a day number since the unix epoch.
+///
+/// See <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn civil_from_days(days: u64) -> (u64, u8, u8) {
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z % 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+fn zip_err(err: zip::result::ZipError) -> PackError {
+    match err {
+        zip::result::ZipError::Io(err) => PackError::Io(err),
+        other => PackError::InvalidZip {
+            reason: other.to_string(),
+        },
+    }
+}
+
+impl core::str::FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stored" => Ok(Self::Stored),
+            "deflate" => Ok(Self::Deflate),
+            _ => Err(format!("Unknown compression {s}")),
+        }
+    }
+}
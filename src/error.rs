@@ -0,0 +1,130 @@
+use std::{fmt, io, path::PathBuf};
+
+/// Everything that can go wrong while creating a polyglot.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PackError {
+    /// The input module could not be parsed.
+    InvalidWasm(wasmparser::BinaryReaderError),
+    /// An input file could not be read.
+    MissingFile { path: PathBuf, source: io::Error },
+    /// An extra section or the trailing zip without a name.
+    EmptySectionName,
+    /// A section is provided by more than one input while only one is loaded.
+    ConflictingSections { name: String },
+    /// A section name that is written by the packer itself.
+    ReservedSection { name: String },
+    /// The data given for a section is not in the encoding it claims.
+    InvalidSectionData { name: String, reason: String },
+    /// A section we looked for is not part of the module.
+    MissingSection { name: String },
+    /// The input is neither a module nor a document of the `html` target.
+    UnknownFormat,
+    /// The input module was not created by the packer.
+    NotAPolyglot,
+    /// A file name that can not be represented in the output.
+    InvalidPath { path: PathBuf },
+    /// A glob pattern that does not parse.
+    InvalidPattern { pattern: String, reason: String },
+    /// A manifest that does not describe a pack.
+    InvalidManifest { path: PathBuf, reason: String },
+    /// The trailing zip could not be understood.
+    InvalidZip { reason: String },
+    /// The output is no longer valid in one of the formats it should be.
+    BrokenFormat {
+        format: &'static str,
+        reason: String,
+    },
+    /// The hot-reloading stage 1 refetches the file as its module, which the `html` target is not.
+    EditHtmlTarget,
+    /// The command line arguments do not fit together.
+    InvalidArguments { reason: String },
+    /// Writing the output failed.
+    Io(io::Error),
+}
+
+impl PackError {
+    /// Attach the path to an error from reading an input file.
+    pub fn file(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        PackError::MissingFile {
+            path: path.into(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for PackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackError::InvalidWasm(err) => write!(
+                f,
+                "invalid WebAssembly module at offset {:#x}: {}",
+                err.offset(),
+                err.message()
+            ),
+            PackError::MissingFile { path, source } => {
+                write!(f, "can not read `{}`: {source}", path.display())
+            }
+            PackError::EmptySectionName => write!(f, "custom section names must not be empty"),
+            PackError::ConflictingSections { name } => {
+                write!(f, "section `{name}` is provided more than once")
+            }
+            PackError::ReservedSection { name } => {
+                write!(f, "section name `{name}` is reserved for the packer")
+            }
+            PackError::InvalidSectionData { name, reason } => {
+                write!(f, "invalid data for section `{name}`: {reason}")
+            }
+            PackError::MissingSection { name } => write!(f, "no section named `{name}`"),
+            PackError::UnknownFormat => write!(
+                f,
+                "input is neither a WebAssembly module nor an `html` target document"
+            ),
+            PackError::NotAPolyglot => write!(
+                f,
+                "input module was not packed, it does not start with `wah_polyglot_stage0`"
+            ),
+            PackError::InvalidPath { path } => {
+                write!(f, "path `{}` is not valid unicode", path.display())
+            }
+            PackError::InvalidPattern { pattern, reason } => {
+                write!(f, "invalid pattern `{pattern}`: {reason}")
+            }
+            PackError::InvalidManifest { path, reason } => {
+                write!(f, "invalid manifest `{}`: {reason}", path.display())
+            }
+            PackError::InvalidZip { reason } => write!(f, "invalid trailing zip: {reason}"),
+            PackError::BrokenFormat { format, reason } => {
+                write!(f, "output is not valid as {format}: {reason}")
+            }
+            PackError::EditHtmlTarget => {
+                write!(f, "the `edit` stage 1 can only reload the `wasm` target")
+            }
+            PackError::InvalidArguments { reason } => write!(f, "{reason}"),
+            PackError::Io(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for PackError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PackError::InvalidWasm(err) => Some(err),
+            PackError::MissingFile { source, .. } => Some(source),
+            PackError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<wasmparser::BinaryReaderError> for PackError {
+    fn from(err: wasmparser::BinaryReaderError) -> Self {
+        PackError::InvalidWasm(err)
+    }
+}
+
+impl From<io::Error> for PackError {
+    fn from(err: io::Error) -> Self {
+        PackError::Io(err)
+    }
+}
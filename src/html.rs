@@ -0,0 +1,357 @@
+//! The `html` target, which embeds the module as data into a plain HTML page.
+use crate::{Encoding, ModuleCompression, PackError};
+use base64::{engine::general_purpose, Engine as _};
+use std::io::{Read, Write};
+
+/// Bytes of the module in each template, a multiple of 12 so that only the last chunk needs
+/// padding in base64 and base85.
+///
+/// Firefox restricts data URIs to 32MB, and a single string of the whole module would need to be
+/// held in memory next to the decoded bytes. Instead each chunk is decoded on its own while the
+/// module is streamed into the compiler.
+pub(crate) const CHUNK_LEN: usize = 3 << 18;
+
+/// The opening tag of each chunk of the module.
+pub(crate) const CHUNK_START: &str = r#"<template class="wah_data_chunk">"#;
+pub(crate) const CHUNK_END: &str = "</template>";
+
+/// The characters of our base85 variant. Printable ASCII without markup, quotes and `\`.
+const BASE85: &[u8; 85] =
+    b"!#$%()*+-./0123456789:=?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[]^_abcdefghijklmnopqrstuvwxyz{|}~";
+
+/// Seven bit groups which base122 must not emit as themselves.
+///
+/// The HTML parser replaces NUL, normalizes CR to LF, and starts references and markup at the
+/// other two.
+const BASE122_ILLEGAL: [u8; 4] = [b'\0', b'\r', b'&', b'<'];
+
+/// Wrap a finished module into the stage 0 HTML template.
+pub(crate) fn wrap(
+    wasm: &[u8],
+    encoding: Encoding,
+    compression: ModuleCompression,
+    minify: bool,
+) -> Result<Vec<u8>, PackError> {
+    const TEMPLATE: &str = include_str!("stage0-html.html");
+
+    let template = if minify {
+        String::from_utf8(crate::minify::html_scripts(TEMPLATE.as_bytes()))
+            .expect("minifying keeps the template valid UTF-8")
+    } else {
+        TEMPLATE.to_owned()
+    };
+
+    let template = template
+        .replace("__REPLACE_THIS_WITH_ENCODING__", encoding.name())
+        .replace("__REPLACE_THIS_WITH_COMPRESSION__", compression.name())
+        .replace("__REPLACE_THIS_WITH_URI_LOADER__", loader(encoding));
+
+    let (head, tail) = template
+        .split_once("__REPLACE_THIS_WITH_WASM_DATA_CHUNKS__")
+        .expect("stage 0 template has a place for the data");
+
+    let data = compress(compression, wasm)?;
+    let chunks = data.len().div_ceil(CHUNK_LEN);
+    let mut out = Vec::with_capacity(
+        head.len()
+            + tail.len()
+            + data.len() / 4 * 5
+            + chunks * (CHUNK_START.len() + CHUNK_END.len()),
+    );
+
+    out.extend_from_slice(head.as_bytes());
+    for chunk in data.chunks(CHUNK_LEN) {
+        out.extend_from_slice(CHUNK_START.as_bytes());
+        encode(encoding, chunk, &mut out);
+        out.extend_from_slice(CHUNK_END.as_bytes());
+    }
+    out.extend_from_slice(tail.as_bytes());
+
+    Ok(out)
+}
+
+/// The encoding recorded in a page of the `html` target.
+///
+/// Pages without the attribute predate the option and always used base64.
+pub(crate) fn encoding_of(page: &[u8]) -> Result<Encoding, PackError> {
+    match attribute(page, "data-encoding")? {
+        None => Ok(Encoding::Base64),
+        Some(name) => name.parse().map_err(|_| PackError::UnknownFormat),
+    }
+}
+
+/// The compression recorded in a page of the `html` target.
+pub(crate) fn compression_of(page: &[u8]) -> Result<ModuleCompression, PackError> {
+    match attribute(page, "data-compression")? {
+        None | Some("") => Ok(ModuleCompression::None),
+        Some(name) => name.parse().map_err(|_| PackError::UnknownFormat),
+    }
+}
+
+/// The value of the first attribute of that name, as we write it.
+fn attribute<'p>(page: &'p [u8], name: &str) -> Result<Option<&'p str>, PackError> {
+    let needle = format!("{name}=\"");
+    let Some(start) = page
+        .windows(needle.len())
+        .position(|window| window == needle.as_bytes())
+    else {
+        return Ok(None);
+    };
+
+    let value = &page[start + needle.len()..];
+    let len = value
+        .iter()
+        .position(|&b| b == b'"')
+        .ok_or(PackError::UnknownFormat)?;
+
+    std::str::from_utf8(&value[..len])
+        .map(Some)
+        .map_err(|_| PackError::UnknownFormat)
+}
+
+/// Compress the module in the format the browser's `DecompressionStream` expects.
+fn compress(compression: ModuleCompression, wasm: &[u8]) -> Result<Vec<u8>, PackError> {
+    let level = flate2::Compression::best();
+
+    Ok(match compression {
+        ModuleCompression::None => wasm.to_vec(),
+        ModuleCompression::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(vec![], level);
+            encoder.write_all(wasm)?;
+            encoder.finish()?
+        }
+        // The `deflate` of compression streams is the zlib format, not raw deflate.
+        ModuleCompression::Deflate => {
+            let mut encoder = flate2::write::ZlibEncoder::new(vec![], level);
+            encoder.write_all(wasm)?;
+            encoder.finish()?
+        }
+    })
+}
+
+/// Undo the compression of a module taken from a page.
+pub(crate) fn decompress(
+    compression: ModuleCompression,
+    data: Vec<u8>,
+) -> Result<Vec<u8>, PackError> {
+    let mut wasm = vec![];
+    let result = match compression {
+        ModuleCompression::None => return Ok(data),
+        ModuleCompression::Gzip => flate2::read::GzDecoder::new(&data[..]).read_to_end(&mut wasm),
+        ModuleCompression::Deflate => {
+            flate2::read::ZlibDecoder::new(&data[..]).read_to_end(&mut wasm)
+        }
+    };
+
+    result.map_err(|_| PackError::UnknownFormat)?;
+    Ok(wasm)
+}
+
+/// Append the text for some data.
+///
+/// The text never contains `<`, `&`, NUL or CR and so is taken literally by the HTML parser
+/// within a template.
+pub(crate) fn encode(encoding: Encoding, data: &[u8], out: &mut Vec<u8>) {
+    match encoding {
+        Encoding::Base64 => {
+            let start = out.len();
+            out.resize(start + data.len().div_ceil(3) * 4, 0);
+            general_purpose::STANDARD
+                .encode_slice(data, &mut out[start..])
+                .expect("buffer sized for the encoding");
+        }
+        Encoding::Base85 => encode_base85(data, out),
+        Encoding::Base122 => encode_base122(data, out),
+    }
+}
+
+/// Append the data decoded from the text of one template.
+pub(crate) fn decode(encoding: Encoding, text: &[u8], out: &mut Vec<u8>) -> Result<(), PackError> {
+    match encoding {
+        Encoding::Base64 => general_purpose::STANDARD
+            .decode_vec(text, out)
+            .map_err(|_| PackError::UnknownFormat),
+        Encoding::Base85 => decode_base85(text, out),
+        Encoding::Base122 => decode_base122(text, out),
+    }
+}
+
+/// The body of the loader function, turning the text `CHUNK` of one template back into bytes.
+fn loader(encoding: Encoding) -> &'static str {
+    match encoding {
+        // We use the built-in `atob` which is correct for the standard alphabet with padding.
+        //
+        // <https://stackoverflow.com/questions/21797299/convert-base64-string-to-arraybuffer>
+        // There answers are mostly bad, and confidently incorrect.
+        Encoding::Base64 => {
+            "const binary = atob(CHUNK);
+                const view = new Uint8Array(binary.length);
+                for (let i = 0; i < binary.length; i++) view[i] = binary.charCodeAt(i);
+                return view;"
+        }
+        Encoding::Base85 => concat!(
+            "const ALPHABET = '",
+            "!#$%()*+-./0123456789:=?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[]^_abcdefghijklmnopqrstuvwxyz{|}~",
+            "';
+                const digit = new Uint8Array(128);
+                for (let i = 0; i < 85; i++) digit[ALPHABET.charCodeAt(i)] = i;
+                const rest = CHUNK.length % 5;
+                const view = new Uint8Array((CHUNK.length - rest) / 5 * 4 + (rest && rest - 1));
+                let o = 0;
+                for (let i = 0; i < CHUNK.length; i += 5) {
+                    let v = 0;
+                    for (let j = i; j < i + 5; j++) v = v * 85 + (j < CHUNK.length ? digit[CHUNK.charCodeAt(j)] : 84);
+                    for (let s = 24; s >= 0 && o < view.length; s -= 8) view[o++] = (v >>> s) & 0xff;
+                }
+                return view;"
+        ),
+        Encoding::Base122 => {
+            "const ILLEGAL = [0, 13, 38, 60];
+                const view = new Uint8Array(Math.ceil(CHUNK.length * 14 / 8));
+                let o = 0, acc = 0, bits = 0;
+                const push = (group) => {
+                    acc = (acc << 7) | group;
+                    bits += 7;
+                    if (bits >= 8) {
+                        bits -= 8;
+                        view[o++] = acc >> bits;
+                        acc &= (1 << bits) - 1;
+                    }
+                };
+                for (let i = 0; i < CHUNK.length; i++) {
+                    const c = CHUNK.charCodeAt(i);
+                    if (c < 0x80) {
+                        push(c);
+                        continue;
+                    }
+                    push(ILLEGAL[(c >> 8) & 3]);
+                    if (c < 0x400) push(c & 0x7f);
+                }
+                return view.subarray(0, o);"
+        }
+    }
+}
+
+/// Four bytes to five characters, big endian, a final group of `n` bytes to `n + 1` characters.
+fn encode_base85(data: &[u8], out: &mut Vec<u8>) {
+    for group in data.chunks(4) {
+        let mut bytes = [0; 4];
+        bytes[..group.len()].copy_from_slice(group);
+        let mut value = u32::from_be_bytes(bytes);
+
+        let mut digits = [0; 5];
+        for digit in digits.iter_mut().rev() {
+            *digit = BASE85[(value % 85) as usize];
+            value /= 85;
+        }
+
+        out.extend_from_slice(&digits[..group.len() + 1]);
+    }
+}
+
+fn decode_base85(text: &[u8], out: &mut Vec<u8>) -> Result<(), PackError> {
+    for group in text.chunks(5) {
+        if group.len() == 1 {
+            return Err(PackError::UnknownFormat);
+        }
+
+        // Padding with the largest digit rounds up, which the truncated bytes absorb.
+        let mut value = 0u64;
+        for idx in 0..5 {
+            let digit = match group.get(idx) {
+                None => 84,
+                Some(ch) => BASE85
+                    .iter()
+                    .position(|b| b == ch)
+                    .ok_or(PackError::UnknownFormat)?,
+            };
+            value = value * 85 + digit as u64;
+        }
+
+        let value = u32::try_from(value).map_err(|_| PackError::UnknownFormat)?;
+        out.extend_from_slice(&value.to_be_bytes()[..group.len() - 1]);
+    }
+
+    Ok(())
+}
+
+/// The data as a stream of seven bit groups, the last one padded with zeros.
+struct Groups<'d> {
+    data: &'d [u8],
+    bit: usize,
+}
+
+impl Iterator for Groups<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.bit / 8;
+        if byte >= self.data.len() {
+            return None;
+        }
+
+        let high = u16::from(self.data[byte]) << 8;
+        let low = self.data.get(byte + 1).copied().map_or(0, u16::from);
+        let group = ((high | low) >> (9 - self.bit % 8)) & 0x7f;
+
+        self.bit += 7;
+        Some(group as u8)
+    }
+}
+
+/// Seven bits to a byte of ASCII, or fourteen bits to a two byte UTF-8 character.
+///
+/// A group that would be an illegal character is instead stored as the index into
+/// [`BASE122_ILLEGAL`] in a two byte character, together with the next group. At the very end
+/// there may be no next group, marked by setting the third bit of the index. This is the scheme of
+/// <https://blog.kevinalbs.com/base122>, with different illegal characters.
+fn encode_base122(data: &[u8], out: &mut Vec<u8>) {
+    let mut groups = Groups { data, bit: 0 };
+
+    while let Some(group) = groups.next() {
+        let Some(idx) = BASE122_ILLEGAL.iter().position(|&b| b == group) else {
+            out.push(group);
+            continue;
+        };
+
+        let (idx, next) = match groups.next() {
+            Some(next) => (idx as u16, u16::from(next)),
+            None => (idx as u16 | 0b100, 0),
+        };
+
+        // Eleven bits of payload, the fixed bit avoids overlong encodings.
+        let char = idx << 8 | 0x80 | next;
+        out.push(0xc0 | (char >> 6) as u8);
+        out.push(0x80 | (char & 0x3f) as u8);
+    }
+}
+
+fn decode_base122(text: &[u8], out: &mut Vec<u8>) -> Result<(), PackError> {
+    let (mut acc, mut bits) = (0u16, 0u32);
+    let mut push = |group: u8| {
+        acc = acc << 7 | u16::from(group);
+        bits += 7;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    };
+
+    let mut text = text.iter();
+    while let Some(&byte) = text.next() {
+        if byte < 0x80 {
+            push(byte);
+            continue;
+        }
+
+        let trail = text.next().ok_or(PackError::UnknownFormat)?;
+        let char = u16::from(byte & 0x1f) << 6 | u16::from(trail & 0x3f);
+        push(BASE122_ILLEGAL[usize::from(char >> 8 & 0b11)]);
+        if char >> 8 & 0b100 == 0 {
+            push((char & 0x7f) as u8);
+        }
+    }
+
+    Ok(())
+}
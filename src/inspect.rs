@@ -0,0 +1,156 @@
+//! Look into an existing polyglot, the reverse direction of the [`Packer`][crate::Packer].
+use crate::PackError;
+use std::{borrow::Cow, ops::Range};
+
+/// One section of a (packed) module.
+#[derive(Clone, Debug)]
+pub struct SectionInfo {
+    /// The section id, `0` for custom sections.
+    pub id: u8,
+    /// The name of a custom section, or the conventional name of a standard section.
+    pub name: String,
+    /// The range of the section's data within the module.
+    ///
+    /// For custom sections this excludes the encoded name.
+    pub range: Range<usize>,
+}
+
+impl SectionInfo {
+    pub fn is_custom(&self) -> bool {
+        self.id == 0
+    }
+
+    /// Does the section start like a zip archive?
+    pub fn is_zip(&self, wasm: &[u8]) -> bool {
+        wasm[self.range.clone()].starts_with(b"PK\x03\x04")
+    }
+}
+
+/// Get the WebAssembly module from a packed file.
+///
+/// The `wasm` target is a module already. For the `html` target we decode the module from the
+/// chunks of data in the page, or the single `wah_data_uri` template of earlier versions.
+pub fn inner_module(file: &[u8]) -> Result<Cow<'_, [u8]>, PackError> {
+    use crate::html::{CHUNK_END, CHUNK_START};
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+    if file.starts_with(b"\0asm") {
+        return Ok(Cow::Borrowed(file));
+    }
+
+    const LEGACY_START: &[u8] = b"<template id=\"wah_data_uri\">";
+
+    let encoding = crate::html::encoding_of(file)?;
+    let mut wasm = vec![];
+    let mut rest = file;
+    while let Some(start) = find(rest, CHUNK_START.as_bytes()) {
+        let data = &rest[start + CHUNK_START.len()..];
+        let len = find(data, CHUNK_END.as_bytes()).ok_or(PackError::UnknownFormat)?;
+
+        crate::html::decode(encoding, &data[..len], &mut wasm)?;
+        rest = &data[len..];
+    }
+
+    if !wasm.is_empty() {
+        let compression = crate::html::compression_of(file)?;
+        return Ok(Cow::Owned(crate::html::decompress(compression, wasm)?));
+    }
+
+    let start = find(file, LEGACY_START).ok_or(PackError::UnknownFormat)? + LEGACY_START.len();
+    let len = find(&file[start..], CHUNK_END.as_bytes()).ok_or(PackError::UnknownFormat)?;
+    let uri = &file[start..][..len];
+
+    let comma = uri
+        .iter()
+        .position(|&b| b == b',')
+        .ok_or(PackError::UnknownFormat)?;
+
+    let wasm = STANDARD
+        .decode(&uri[comma + 1..])
+        .map_err(|_| PackError::UnknownFormat)?;
+
+    Ok(Cow::Owned(wasm))
+}
+
+/// List all sections of a module, in order.
+pub fn sections(wasm: &[u8]) -> Result<Vec<SectionInfo>, PackError> {
+    let parser = wasmparser::Parser::default();
+    let mut sections = vec![];
+
+    for payload in parser.parse_all(wasm) {
+        let payload = payload?;
+
+        if let wasmparser::Payload::CustomSection(reader) = &payload {
+            let start = reader.data_offset();
+
+            sections.push(SectionInfo {
+                id: 0,
+                name: reader.name().to_owned(),
+                range: start..start + reader.data().len(),
+            });
+        } else if let Some((id, range)) = payload.as_section() {
+            sections.push(SectionInfo {
+                id,
+                name: standard_name(id).to_owned(),
+                range,
+            });
+        }
+    }
+
+    Ok(sections)
+}
+
+/// Find the data of the first custom section with a name, or standard section with that
+/// conventional name.
+pub fn section<'w>(wasm: &'w [u8], name: &str) -> Result<&'w [u8], PackError> {
+    let section = sections(wasm)?
+        .into_iter()
+        .find(|section| section.name == name)
+        .ok_or_else(|| PackError::MissingSection {
+            name: name.to_owned(),
+        })?;
+
+    Ok(&wasm[section.range])
+}
+
+/// The entries of the `wah_polyglot_metadata` section, such as the `stage1` loader.
+///
+/// Files from versions without metadata have no entries.
+pub fn metadata(wasm: &[u8]) -> Result<Vec<(String, String)>, PackError> {
+    let data = match section(wasm, "wah_polyglot_metadata") {
+        Ok(data) => data,
+        Err(PackError::MissingSection { .. }) => return Ok(vec![]),
+        Err(err) => return Err(err),
+    };
+
+    Ok(String::from_utf8_lossy(data)
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.to_owned(), value.to_owned()))
+        .collect())
+}
+
+fn standard_name(id: u8) -> &'static str {
+    match id {
+        1 => "type",
+        2 => "import",
+        3 => "function",
+        4 => "table",
+        5 => "memory",
+        6 => "global",
+        7 => "export",
+        8 => "start",
+        9 => "element",
+        10 => "code",
+        11 => "data",
+        12 => "datacount",
+        13 => "tag",
+        _ => "unknown",
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
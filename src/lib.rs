@@ -0,0 +1,913 @@
+//! Adds a webpage to a WebAssembly module, making it self-hosted.
+//!
+//! The [`Packer`] combines a stage 2 loader, the module itself and any further payloads into one
+//! polyglot file. The `wasm-as-html` binary is a thin command line wrapper around it, but build
+//! scripts can use it directly without going through temporary files.
+//!
+//! ```no_run
+//! use wasm_as_html::{Packer, Target};
+//!
+//! let stage2 = std::fs::read("wasi-loader/out.js")?;
+//! let wasm = std::fs::read("todomvc_bg.wasm")?;
+//!
+//! let html = Packer::new(stage2, wasm)
+//!     .index_html(std::fs::read("index.html")?)
+//!     .target(Target::Html)
+//!     .pack()?;
+//! # Ok::<_, wasm_as_html::PackError>(())
+//! ```
+mod archive;
+pub mod data;
+mod error;
+mod html;
+pub mod inspect;
+mod minify;
+pub mod verify;
+
+pub use error::PackError;
+
+use std::{borrow::Cow, io::Write};
+
+const STAGE0: &[u8] = include_bytes!("stage0-wasm.html");
+const STAGE1: &[u8] = include_bytes!("stage1.js");
+const STAGE1_EDIT: &[u8] = include_bytes!("stage1-edit.js");
+const STAGE1_INSTANTIATE: &[u8] = include_bytes!("stage1-instantiate.js");
+const STAGE1_WASI: &[u8] = include_bytes!("stage1-wasi.js");
+const STAGE1_WORKER: &[u8] = include_bytes!("stage1-worker.js");
+const STAGE1_DEBUG: &[u8] = include_bytes!("stage1-debug.js");
+
+const STAGE0_NAME: &str = "wah_polyglot_stage0";
+
+// The module header, the section id and the length of the stage 0 section are read as text
+// before its HTML. Lengths whose encoding contains markup would break the page.
+const _: () = assert!(
+    verify::is_leb128_inert(stage0_section_len(STAGE0.len())),
+    "the length of `stage0-wasm.html` must not encode to `<` or `&`, add or remove some bytes",
+);
+
+/// The length of the stage 0 section with this much HTML, including its name.
+const fn stage0_section_len(html_len: usize) -> usize {
+    1 + STAGE0_NAME.len() + html_len
+}
+
+/// Builder for a polyglot document.
+#[derive(Clone, Debug)]
+pub struct Packer {
+    stage2: Vec<u8>,
+    wasm: Vec<u8>,
+    index_html: Option<Vec<u8>>,
+    sections: Vec<Section>,
+    trailing_zip: Option<Section>,
+    target: Target,
+    encoding: Encoding,
+    compression: ModuleCompression,
+    stage1: Stage1,
+    minify: bool,
+    verify: bool,
+    source_date: Option<u64>,
+    strip_debug: bool,
+    strip_custom: Vec<glob::Pattern>,
+    keep_custom: Vec<glob::Pattern>,
+    debug_info: DebugInfo,
+    force: bool,
+}
+
+/// Where the DWARF `.debug_*` sections of the module go.
+///
+/// Browsers load the module from memory, so devtools can not find debug information next to it.
+/// When it is split off, a hook before stage 1 compiles the module again with an
+/// `external_debug_info` section that refers to it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum DebugInfo {
+    /// Leave the sections in the module.
+    #[default]
+    Keep,
+    /// Move the sections into a module of their own, in the `wah_polyglot_debug` section.
+    Embed,
+    /// Move the sections into a separate file, see [`Packer::debug_module`], at this URL.
+    ///
+    /// A relative URL is resolved against the document.
+    Sidecar(String),
+}
+
+/// A custom section to add to the module.
+#[derive(Clone, Debug)]
+pub struct Section {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// How to wrap the output Web Assembly module.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Target {
+    /// Enter execution from an initial section that looks like valid HTML.
+    ///
+    /// This target is NOT compatible with serving from a file in Chromium, as it requires access
+    /// to the file's own file-URI via `fetch`.
+    #[default]
+    WasmPlusHtml,
+    /// Encode the resulting module as a blob and load it.
+    ///
+    /// This target is generally compatible with web browsers but obviously the output file is no
+    /// longer a WebAssembly module itself.
+    Html,
+}
+
+/// The stage 1 loader, which prepares the page and hands control to the stage 2 payload.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Stage1 {
+    /// Stage 2 is a wasm-bindgen module, called with a response resolving to the module.
+    #[default]
+    WasmBindgen,
+    /// Instantiate the module without JS glue, stage 2 may provide the imports.
+    Instantiate,
+    /// Run the module as a WASI command against a minimal system in the page.
+    Wasi,
+    /// Run stage 2 in a module worker and send it the compiled module.
+    Worker,
+    /// Experimental. Like the default, but hot-reload when the file changes. Only for the `wasm`
+    /// target.
+    Edit,
+    /// A loader of your own, a JS module with the same default export as the others.
+    Custom(Vec<u8>),
+}
+
+/// How the `html` target writes the module as text.
+///
+/// All encodings avoid the characters that would end the template holding the data, or be
+/// changed by the HTML parser.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// Standard base64, a third larger than the module and decoded by the browser.
+    #[default]
+    Base64,
+    /// Four bytes in five characters of printable ASCII, a quarter larger than the module.
+    Base85,
+    /// Seven bits per byte of UTF-8, about a seventh larger than the module.
+    Base122,
+}
+
+/// How the `html` target compresses the module before encoding it.
+///
+/// The page decompresses it with the `DecompressionStream` of the browser.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ModuleCompression {
+    #[default]
+    None,
+    Gzip,
+    /// The zlib format, which compression streams call `deflate`.
+    Deflate,
+}
+
+impl Packer {
+    /// The section name used for the trailing zip, unless overridden.
+    pub const DEFAULT_ZIP_SECTION: &'static str = "wah_polyglot_stage2_data";
+
+    /// The sections that the packer writes itself, when their inputs are given.
+    pub const RESERVED_SECTIONS: &'static [&'static str] = &[
+        STAGE0_NAME,
+        "wah_polyglot_stage1",
+        "wah_polyglot_metadata",
+        "wah_polyglot_stage1_html",
+        "wah_polyglot_stage2",
+        "wah_polyglot_stage1_next",
+        "wah_polyglot_debug",
+        "wah_polyglot_debug_url",
+        Self::DEFAULT_ZIP_SECTION,
+    ];
+
+    /// Start packing a module with the stage 2 loader payload, a JS module.
+    ///
+    /// The stage 2 payload is your module that gains control of execution and is invoked with a
+    /// fake request that resolves to full WASM module, after the page has been replaced with the
+    /// indicated `index.html`.
+    pub fn new(stage2: impl Into<Vec<u8>>, wasm: impl Into<Vec<u8>>) -> Self {
+        Packer {
+            stage2: stage2.into(),
+            wasm: wasm.into(),
+            index_html: None,
+            sections: vec![],
+            trailing_zip: None,
+            target: Target::default(),
+            encoding: Encoding::default(),
+            compression: ModuleCompression::default(),
+            stage1: Stage1::default(),
+            minify: false,
+            verify: false,
+            source_date: None,
+            strip_debug: false,
+            strip_custom: vec![],
+            keep_custom: vec![],
+            debug_info: DebugInfo::Keep,
+            force: false,
+        }
+    }
+
+    /// Take apart a previously packed file, of either target, to pack it again.
+    ///
+    /// All `wah_polyglot_*` sections are recovered as the payloads of the packer, as is a trailing
+    /// zip in the final section. All other sections of the module are preserved byte-for-byte.
+    /// The stage 0 and stage 1 loaders are replaced by those of this version of the packer.
+    pub fn from_polyglot(file: &[u8]) -> Result<Self, PackError> {
+        let wasm = inspect::inner_module(file)?;
+        let (target, encoding, compression) = match wasm {
+            Cow::Borrowed(_) => (
+                Target::WasmPlusHtml,
+                Encoding::default(),
+                Default::default(),
+            ),
+            Cow::Owned(_) => (
+                Target::Html,
+                html::encoding_of(file)?,
+                html::compression_of(file)?,
+            ),
+        };
+
+        let sections = inspect::sections(&wasm)?;
+        if sections.first().map(|s| s.name.as_str()) != Some("wah_polyglot_stage0") {
+            return Err(PackError::NotAPolyglot);
+        }
+
+        let mut stage2 = None;
+        let mut index_html = None;
+        let mut stage1 = None;
+        let mut stage1_next = None;
+        let mut minify = false;
+        let mut debug_info = DebugInfo::Keep;
+        let mut extra = vec![];
+        let mut trailing_zip = None;
+        let mut module = wasm_encoder::Module::new();
+
+        let last = sections.len() - 1;
+        for (idx, section) in sections.iter().enumerate() {
+            let data = &wasm[section.range.clone()];
+            let is_trailing_zip = idx == last
+                && section.is_custom()
+                && archive::ends_with_zip(&wasm, section.range.start);
+
+            if !section.is_custom() {
+                module.section(&wasm_encoder::RawSection {
+                    id: section.id,
+                    data,
+                });
+                continue;
+            }
+
+            if !section.name.starts_with("wah_polyglot_") && !is_trailing_zip {
+                module.section(&wasm_encoder::CustomSection {
+                    name: &section.name,
+                    data,
+                });
+                continue;
+            }
+
+            let payload = Section {
+                name: section.name.clone(),
+                data: data.to_vec(),
+            };
+
+            match section.name.as_str() {
+                // Only the minified stage 0 differs, even with a loader of our own.
+                "wah_polyglot_stage0" => minify = data != STAGE0,
+                "wah_polyglot_metadata" => {}
+                "wah_polyglot_stage1" => stage1 = Some(data),
+                "wah_polyglot_stage1_next" => stage1_next = Some(data),
+                "wah_polyglot_debug" => {
+                    // Put the split sections back, they are split off again when packing.
+                    for dwarf in inspect::sections(data)? {
+                        module.section(&wasm_encoder::CustomSection {
+                            name: &dwarf.name,
+                            data: &data[dwarf.range],
+                        });
+                    }
+                    debug_info = DebugInfo::Embed;
+                }
+                "wah_polyglot_debug_url" => {
+                    let url = String::from_utf8_lossy(data).into_owned();
+                    debug_info = DebugInfo::Sidecar(url);
+                }
+                "wah_polyglot_stage1_html" => index_html = Some(payload.data),
+                "wah_polyglot_stage2" if stage2.is_none() => stage2 = Some(payload.data),
+                _ if is_trailing_zip => trailing_zip = Some(payload),
+                _ => extra.push(payload),
+            }
+        }
+
+        let stage2 = stage2.ok_or_else(|| PackError::MissingSection {
+            name: "wah_polyglot_stage2".into(),
+        })?;
+
+        // Behind the hook for split debug information, the actual loader is the next one.
+        let stage1 = stage1_next
+            .or(stage1)
+            .ok_or_else(|| PackError::MissingSection {
+                name: "wah_polyglot_stage1".into(),
+            })?;
+        let stage1 = Stage1::recover(stage1);
+
+        Ok(Packer {
+            stage2,
+            wasm: module.finish(),
+            index_html,
+            sections: extra,
+            trailing_zip,
+            target,
+            encoding,
+            compression,
+            stage1,
+            minify,
+            verify: false,
+            source_date: None,
+            strip_debug: false,
+            strip_custom: vec![],
+            keep_custom: vec![],
+            debug_info,
+            force: false,
+        })
+    }
+
+    /// Replace the stage 2 loader payload.
+    pub fn stage2(mut self, stage2: impl Into<Vec<u8>>) -> Self {
+        self.stage2 = stage2.into();
+        self
+    }
+
+    /// An HTML page to use when invoking the loader. Setup by the stage 1 loader.
+    pub fn index_html(mut self, html: impl Into<Vec<u8>>) -> Self {
+        self.index_html = Some(html.into());
+        self
+    }
+
+    /// Add a custom section after the sections of the module.
+    pub fn section(mut self, name: impl Into<String>, data: impl Into<Vec<u8>>) -> Self {
+        self.sections.push(Section {
+            name: name.into(),
+            data: data.into(),
+        });
+        self
+    }
+
+    /// Add a custom section, replacing all previously added sections of the same name.
+    pub fn replace_section(mut self, name: impl Into<String>, data: impl Into<Vec<u8>>) -> Self {
+        let name = name.into();
+        let mut data = Some(data.into());
+
+        // The first section of that name takes the data, all others are dropped.
+        self.sections.retain_mut(|section| {
+            if section.name != name {
+                return true;
+            }
+
+            match data.take() {
+                Some(data) => {
+                    section.data = data;
+                    true
+                }
+                None => false,
+            }
+        });
+
+        if let Some(data) = data {
+            self.sections.push(Section { name, data });
+        }
+
+        self
+    }
+
+    /// A zip file to attach as the final section, named `wah_polyglot_stage2_data`.
+    pub fn trailing_zip(self, data: impl Into<Vec<u8>>) -> Self {
+        self.trailing_zip_as(Self::DEFAULT_ZIP_SECTION, data)
+    }
+
+    /// A zip file to attach as the final section, with a customized section name.
+    pub fn trailing_zip_as(mut self, name: impl Into<String>, data: impl Into<Vec<u8>>) -> Self {
+        self.trailing_zip = Some(Section {
+            name: name.into(),
+            data: data.into(),
+        });
+        self
+    }
+
+    /// Choose the 'stage 0' entry point into setting up the web assembly.
+    pub fn target(mut self, target: Target) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Choose how the module is written into the page of the `html` target.
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Compress the module in the page of the `html` target, to be decompressed by the browser.
+    pub fn compress(mut self, compression: ModuleCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Choose the stage 1 loader, which prepares the page and hands control to stage 2.
+    pub fn stage1(mut self, stage1: Stage1) -> Self {
+        self.stage1 = stage1;
+        self
+    }
+
+    /// Experimental. Use the stage 1 loader which hot-reloads when the file changes.
+    ///
+    /// Shorthand for [`Stage1::Edit`], turning it off goes back to the default loader. The loader
+    /// refetches the file as its module, so packing it for the `html` target fails.
+    pub fn edit(mut self, edit: bool) -> Self {
+        if edit {
+            self.stage1 = Stage1::Edit;
+        } else if self.stage1 == Stage1::Edit {
+            self.stage1 = Stage1::default();
+        }
+
+        self
+    }
+
+    /// Make the output depend only on the contents of the inputs.
+    ///
+    /// All timestamps in the trailing zip are replaced by this time, in seconds since the unix
+    /// epoch, conventionally taken from `SOURCE_DATE_EPOCH`. Extra sections are ordered by their
+    /// name, sections of the same name keep their relative order.
+    pub fn reproducible(mut self, source_date: u64) -> Self {
+        self.source_date = Some(source_date);
+        self
+    }
+
+    /// Strip comments and indentation from the scripts of stage 0 and stage 1.
+    pub fn minify(mut self, minify: bool) -> Self {
+        self.minify = minify;
+        self
+    }
+
+    /// Check the output with [`verify::verify`] before returning it.
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Drop the debug information of the module.
+    ///
+    /// These are the DWARF `.debug_*` sections, the `name` section and the `sourceMappingURL` and
+    /// `external_debug_info` references.
+    pub fn strip_debug(mut self, strip: bool) -> Self {
+        self.strip_debug = strip;
+        self
+    }
+
+    /// Drop the custom sections of the module whose name matches a glob pattern.
+    pub fn strip_custom(mut self, pattern: &str) -> Result<Self, PackError> {
+        self.strip_custom.push(name_pattern(pattern)?);
+        Ok(self)
+    }
+
+    /// Keep the custom sections of the module whose name matches a glob pattern.
+    ///
+    /// This takes precedence over [`Packer::strip_debug`] and [`Packer::strip_custom`].
+    pub fn keep_custom(mut self, pattern: &str) -> Result<Self, PackError> {
+        self.keep_custom.push(name_pattern(pattern)?);
+        Ok(self)
+    }
+
+    /// Add sections even if their names are reserved for the packer.
+    ///
+    /// Stage 1 then finds them besides, or instead of, the packer's own sections.
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Split the DWARF sections off the module so that browser devtools can find them.
+    pub fn debug_info(mut self, debug_info: DebugInfo) -> Self {
+        self.debug_info = debug_info;
+        self
+    }
+
+    /// A module with only the DWARF sections, as split off by [`Packer::debug_info`].
+    ///
+    /// This is the file to provide at the URL of [`DebugInfo::Sidecar`].
+    pub fn debug_module(&self) -> Result<Vec<u8>, PackError> {
+        let mut encoder = wasm_encoder::Module::new();
+
+        for section in inspect::sections(&self.wasm)? {
+            if section.is_custom() && is_dwarf(&section.name) && !self.strips(&section.name) {
+                encoder.section(&wasm_encoder::CustomSection {
+                    name: &section.name,
+                    data: &self.wasm[section.range],
+                });
+            }
+        }
+
+        Ok(encoder.finish())
+    }
+
+    /// The custom sections of the module that are dropped when packing.
+    pub fn stripped(&self) -> Result<Vec<inspect::SectionInfo>, PackError> {
+        let mut sections = inspect::sections(&self.wasm)?;
+        sections.retain(|section| section.is_custom() && self.strips(&section.name));
+        Ok(sections)
+    }
+
+    /// Create the polyglot file.
+    pub fn pack(&self) -> Result<Vec<u8>, PackError> {
+        self.wrap(self.module()?, self.target)
+    }
+
+    /// Create one polyglot file for each of the targets, in the same order.
+    ///
+    /// The module is built only once, so this is cheaper than packing for each target in turn.
+    /// The target chosen with [`Packer::target`] is ignored.
+    pub fn pack_targets(&self, targets: &[Target]) -> Result<Vec<Vec<u8>>, PackError> {
+        let module = self.module()?;
+        targets
+            .iter()
+            .map(|&target| self.wrap(module.clone(), target))
+            .collect()
+    }
+
+    /// Create the module with all of our sections, as written by the `wasm` target.
+    fn module(&self) -> Result<Vec<u8>, PackError> {
+        self.check_sections()?;
+
+        let mut encoder = wasm_encoder::Module::new();
+
+        encoder.section(&wasm_encoder::CustomSection {
+            name: STAGE0_NAME,
+            // Html designed to terminate processing into further WASM sections. This is the only
+            // section that needs to be placed specifically at the start. All other sections are
+            // then parsed from the module.
+            data: &self.stage0(),
+        });
+
+        // The actual (document) loader that prepares inputs and control for stage 2. The hook for
+        // split debug information runs first, if any, and then hands over to it.
+        if self.debug_info == DebugInfo::Keep {
+            encoder.section(&wasm_encoder::CustomSection {
+                name: "wah_polyglot_stage1",
+                data: &self.stage1_source(),
+            });
+        } else {
+            encoder.section(&wasm_encoder::CustomSection {
+                name: "wah_polyglot_stage1",
+                data: &self.builtin_source(STAGE1_DEBUG),
+            });
+            encoder.section(&wasm_encoder::CustomSection {
+                name: "wah_polyglot_stage1_next",
+                data: &self.stage1_source(),
+            });
+        }
+
+        // Not read by any stage, tells tools how the file was packed.
+        encoder.section(&wasm_encoder::CustomSection {
+            name: "wah_polyglot_metadata",
+            data: format!("stage1={}\n", self.stage1.name()).as_bytes(),
+        });
+
+        if let Some(index_html) = &self.index_html {
+            encoder.section(&wasm_encoder::CustomSection {
+                name: "wah_polyglot_stage1_html",
+                data: index_html,
+            });
+        }
+
+        encoder.section(&wasm_encoder::CustomSection {
+            name: "wah_polyglot_stage2",
+            data: &self.stage2,
+        });
+
+        for section in inspect::sections(&self.wasm)? {
+            let data = &self.wasm[section.range.clone()];
+
+            if !section.is_custom() {
+                encoder.section(&wasm_encoder::RawSection {
+                    id: section.id,
+                    data,
+                });
+            } else if !self.strips(&section.name) && !self.splits(&section.name) {
+                encoder.section(&wasm_encoder::CustomSection {
+                    name: &section.name,
+                    data,
+                });
+            }
+        }
+
+        match &self.debug_info {
+            DebugInfo::Keep => {}
+            DebugInfo::Embed => {
+                encoder.section(&wasm_encoder::CustomSection {
+                    name: "wah_polyglot_debug",
+                    data: &self.debug_module()?,
+                });
+            }
+            DebugInfo::Sidecar(url) => {
+                encoder.section(&wasm_encoder::CustomSection {
+                    name: "wah_polyglot_debug_url",
+                    data: url.as_bytes(),
+                });
+            }
+        }
+
+        let mut sections: Vec<&Section> = self.sections.iter().collect();
+        if self.source_date.is_some() {
+            sections.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+
+        for extra in sections {
+            encoder.section(&wasm_encoder::CustomSection {
+                name: &extra.name,
+                data: &extra.data,
+            });
+        }
+
+        if let Some(zip) = &self.trailing_zip {
+            let data = match self.source_date {
+                None => relocate_zip(encoder.as_slice().len(), zip)?,
+                Some(secs) => {
+                    let mut zip = zip.clone();
+                    let time = std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+                    let unix_time = u32::try_from(secs).unwrap_or(u32::MAX);
+                    archive::normalize_times(&mut zip.data, unix_time, data::zip_time(time))?;
+                    relocate_zip(encoder.as_slice().len(), &zip)?
+                }
+            };
+            encoder.section(&wasm_encoder::CustomSection {
+                name: &zip.name,
+                data: &data,
+            });
+        }
+
+        Ok(encoder.finish())
+    }
+
+    fn wrap(&self, module: Vec<u8>, target: Target) -> Result<Vec<u8>, PackError> {
+        if self.stage1 == Stage1::Edit && target == Target::Html {
+            return Err(PackError::EditHtmlTarget);
+        }
+
+        let output = match target {
+            Target::WasmPlusHtml => module,
+            Target::Html => html::wrap(&module, self.encoding, self.compression, self.minify)?,
+        };
+
+        if self.verify {
+            verify::verify(&output)?;
+        }
+
+        Ok(output)
+    }
+
+    /// Create the polyglot file and write it out.
+    pub fn write_to(&self, mut out: impl Write) -> Result<(), PackError> {
+        out.write_all(&self.pack()?)?;
+        Ok(())
+    }
+
+    /// Is a custom section of the module dropped?
+    fn strips(&self, name: &str) -> bool {
+        let is_debug =
+            is_dwarf(name) || matches!(name, "name" | "sourceMappingURL" | "external_debug_info");
+
+        let strip = (self.strip_debug && is_debug)
+            || self
+                .strip_custom
+                .iter()
+                .any(|pattern| pattern.matches(name));
+
+        strip && !self.keep_custom.iter().any(|pattern| pattern.matches(name))
+    }
+
+    /// Is a custom section of the module moved into the split debug information?
+    fn splits(&self, name: &str) -> bool {
+        self.debug_info != DebugInfo::Keep && is_dwarf(name)
+    }
+
+    fn stage0(&self) -> Cow<'static, [u8]> {
+        if !self.minify {
+            return Cow::Borrowed(STAGE0);
+        }
+
+        // Pad the trailing comment until the length is out of the markup range again.
+        let mut html = minify::html_scripts(STAGE0);
+        while !verify::is_leb128_inert(stage0_section_len(html.len())) {
+            html.push(b' ');
+        }
+
+        Cow::Owned(html)
+    }
+
+    fn stage1_source(&self) -> Cow<'_, [u8]> {
+        match &self.stage1 {
+            Stage1::Custom(source) => Cow::Borrowed(source),
+            builtin => self.builtin_source(builtin.source()),
+        }
+    }
+
+    fn builtin_source<'s>(&self, source: &'s [u8]) -> Cow<'s, [u8]> {
+        if self.minify {
+            Cow::Owned(minify::js(source))
+        } else {
+            Cow::Borrowed(source)
+        }
+    }
+
+    /// Reject extra sections which would shadow one of the sections we emit ourselves, unless
+    /// forced.
+    fn check_sections(&self) -> Result<(), PackError> {
+        let zip_name = self.trailing_zip.as_ref().map(|zip| zip.name.as_str());
+        let mut names = self.sections.iter().map(|extra| extra.name.as_str());
+
+        if zip_name == Some("") || names.any(str::is_empty) {
+            return Err(PackError::EmptySectionName);
+        }
+
+        if self.force {
+            return Ok(());
+        }
+
+        let reserved = |name: &str| Self::RESERVED_SECTIONS.contains(&name);
+        if let Some(name) = zip_name.filter(|&name| name != Self::DEFAULT_ZIP_SECTION) {
+            if reserved(name) {
+                return Err(PackError::ReservedSection { name: name.into() });
+            }
+        }
+
+        for extra in &self.sections {
+            if reserved(&extra.name) {
+                return Err(PackError::ReservedSection {
+                    name: extra.name.clone(),
+                });
+            }
+
+            if zip_name == Some(extra.name.as_str()) {
+                return Err(PackError::ConflictingSections {
+                    name: extra.name.clone(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn is_dwarf(name: &str) -> bool {
+    name.starts_with(".debug_")
+}
+
+fn name_pattern(pattern: &str) -> Result<glob::Pattern, PackError> {
+    glob::Pattern::new(pattern).map_err(|err| PackError::InvalidPattern {
+        pattern: pattern.to_owned(),
+        reason: err.msg.to_owned(),
+    })
+}
+
+/// Rewrite the zip for its position in the file, as the last section after the module.
+fn relocate_zip(module_len: usize, zip: &Section) -> Result<Vec<u8>, PackError> {
+    fn leb_len(n: usize) -> usize {
+        (n.max(1).ilog2() as usize) / 7 + 1
+    }
+
+    // The position depends on the encoded length of the relocated data. It only changes if we
+    // need to add zip64 records, so this settles after very few rounds.
+    let mut data_len = zip.data.len();
+    loop {
+        let name_len = leb_len(zip.name.len()) + zip.name.len();
+        let position = module_len + 1 + leb_len(name_len + data_len) + name_len;
+        let data = archive::relocate(&zip.data, position as u64)?;
+
+        if data.len() == data_len {
+            return Ok(data);
+        }
+
+        data_len = data.len();
+    }
+}
+
+impl Target {
+    /// The name of the target on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::WasmPlusHtml => "wasm",
+            Self::Html => "html",
+        }
+    }
+}
+
+impl core::str::FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wasm" => Ok(Self::WasmPlusHtml),
+            "wasm+html" => Ok(Self::WasmPlusHtml),
+            "html" => Ok(Self::Html),
+            _ => Err(format!("Unknown target selection {s}")),
+        }
+    }
+}
+
+impl Encoding {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::Base64 => "base64",
+            Self::Base85 => "base85",
+            Self::Base122 => "base122",
+        }
+    }
+}
+
+impl core::str::FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "base64" => Ok(Self::Base64),
+            "base85" => Ok(Self::Base85),
+            "base122" => Ok(Self::Base122),
+            _ => Err(format!("Unknown encoding {s}")),
+        }
+    }
+}
+
+impl ModuleCompression {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::None => "",
+            Self::Gzip => "gzip",
+            Self::Deflate => "deflate",
+        }
+    }
+}
+
+impl core::str::FromStr for ModuleCompression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "gzip" => Ok(Self::Gzip),
+            "deflate" => Ok(Self::Deflate),
+            _ => Err(format!("Unknown compression {s}")),
+        }
+    }
+}
+
+impl Stage1 {
+    const BUILTIN: [Stage1; 5] = [
+        Stage1::WasmBindgen,
+        Stage1::Instantiate,
+        Stage1::Wasi,
+        Stage1::Worker,
+        Stage1::Edit,
+    ];
+
+    /// The name recorded in the metadata of the packed file.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::WasmBindgen => "wasm-bindgen",
+            Self::Instantiate => "instantiate",
+            Self::Wasi => "wasi",
+            Self::Worker => "worker",
+            Self::Edit => "edit",
+            Self::Custom(_) => "custom",
+        }
+    }
+
+    fn source(&self) -> &[u8] {
+        match self {
+            Self::WasmBindgen => STAGE1,
+            Self::Instantiate => STAGE1_INSTANTIATE,
+            Self::Wasi => STAGE1_WASI,
+            Self::Worker => STAGE1_WORKER,
+            Self::Edit => STAGE1_EDIT,
+            Self::Custom(source) => source,
+        }
+    }
+
+    /// Identify a packed loader, minified or not.
+    fn recover(data: &[u8]) -> Self {
+        for builtin in Self::BUILTIN {
+            if data == builtin.source() || data == minify::js(builtin.source()) {
+                return builtin;
+            }
+        }
+
+        Self::Custom(data.to_vec())
+    }
+}
+
+impl core::str::FromStr for Stage1 {
+    type Err = String;
+
+    /// Parse the name of a built-in loader, except for the experimental one.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wasm-bindgen" => Ok(Self::WasmBindgen),
+            "instantiate" => Ok(Self::Instantiate),
+            "wasi" => Ok(Self::Wasi),
+            "worker" => Ok(Self::Worker),
+            _ => Err(format!("Unknown stage 1 loader {s}")),
+        }
+    }
+}
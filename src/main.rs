@@ -1,175 +1,837 @@
-use clap::Parser;
-use std::{io::Read, io::Write, path::PathBuf};
+mod manifest;
+mod serve;
+mod watch;
 
-fn main() -> Result<(), std::io::Error> {
-    let args = Args::parse();
+use clap::{Parser, Subcommand};
+use std::{
+    borrow::Cow,
+    io::{Read, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+use wasm_as_html::{
+    data::{Compression, DataDir},
+    inspect, DebugInfo, Encoding, ModuleCompression, PackError, Packer, Stage1, Target,
+};
 
-    let stage_2 = std::fs::read(&args.stage_2)?;
-    let wasm = match &args.wasm {
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let result = match cli.command {
         None => {
-            let mut stdin = std::io::stdin();
-            let mut data = vec![];
-            stdin.read_to_end(&mut data)?;
-            data
+            let mut args = cli.pack;
+            prepare(&mut args).and_then(|()| run(args))
         }
-        Some(path) => std::fs::read(path)?,
+        Some(Command::Inspect(args)) => run_inspect(args),
+        Some(Command::Extract(args)) => run_extract(args),
+        Some(Command::Repack(args)) => run_repack(args),
+        Some(Command::Serve(args)) => run_serve(args),
     };
 
-    let parser = wasmparser::Parser::default();
-    let mut encoder = wasm_encoder::Module::new();
-
-    encoder.section(&wasm_encoder::CustomSection {
-        name: "wah_polyglot_stage0",
-        // Html designed to terminate processing into further WASM sections. This is the only
-        // section that needs to be placed specifically at the start. All other sections are then
-        // parsed from the module.
-        data: include_bytes!("stage0-wasm.html"),
-    });
-
-    // The actual (document) loader that prepares inputs and control for stage 2.
-    encoder.section(&wasm_encoder::CustomSection {
-        name: "wah_polyglot_stage1",
-        data: if args.edit {
-            assert!(std::env::var_os("WAH_POLYGLOT_EXPERIMENTAL").is_some());
-            include_bytes!("stage1-edit.js")
-        } else {
-            include_bytes!("stage1.js")
-        },
-    });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Complete the arguments from the manifest, and check the sections to add.
+fn prepare(args: &mut Args) -> Result<(), PackError> {
+    if let Some(path) = args.manifest.clone() {
+        manifest::Manifest::load(&path)?.apply(&path, args)?;
+    }
 
-    if let Some(index) = &args.index_html {
-        let index_html = std::fs::read(index)?;
+    resolve_sections(&mut args.extra_section, args.wasm.is_some(), args.force)
+}
+
+/// Check that the sections to add are unique and read the one from stdin, if any.
+///
+/// Stdin is read here once so that repacking on changes sees the same data again.
+fn resolve_sections(
+    sections: &mut [ExtraSection],
+    stdin_free: bool,
+    force: bool,
+) -> Result<(), PackError> {
+    let from_stdin = sections
+        .iter()
+        .filter(|extra| matches!(extra.source, SectionSource::Stdin))
+        .count();
 
-        encoder.section(&wasm_encoder::CustomSection {
-            name: "wah_polyglot_stage1_html",
-            data: &index_html,
+    if from_stdin > 1 || (from_stdin == 1 && !stdin_free) {
+        return Err(PackError::InvalidArguments {
+            reason:
+                "stdin can be read only once, give the module as a path to read a section from it"
+                    .into(),
         });
     }
 
-    encoder.section(&wasm_encoder::CustomSection {
-        name: "wah_polyglot_stage2",
-        data: &stage_2,
-    });
+    for idx in 0..sections.len() {
+        // Reserved names are checked by the packer.
+        let name = &sections[idx].name;
+        if !force && sections[..idx].iter().any(|other| other.name == *name) {
+            return Err(PackError::ConflictingSections { name: name.clone() });
+        }
+
+        if let SectionSource::Stdin = sections[idx].source {
+            let data = sections[idx].data()?;
+            sections[idx].source = SectionSource::Inline(data);
+            sections[idx].encoding = SectionEncoding::Raw;
+        }
+    }
+
+    Ok(())
+}
+
+fn run(args: Args) -> Result<(), PackError> {
+    if args.watch {
+        return run_watch(args);
+    }
+
+    let outputs = outputs(&args)?;
+    let targets: Vec<Target> = outputs.iter().map(|output| output.target).collect();
+    let packer = packer(&args)?;
+    report_stripped(&packer)?;
+    write_sidecar(&packer, &args)?;
+    let files = packer.pack_targets(&targets)?;
+
+    let files: Vec<_> = outputs
+        .iter()
+        .map(|output| output.path.as_deref())
+        .zip(files)
+        .collect();
+
+    write_out(&files, args.compress.is_some())
+}
+
+fn run_watch(args: Args) -> Result<(), PackError> {
+    if args.out.is_none() || args.wasm.is_none() {
+        return Err(PackError::InvalidArguments {
+            reason: "--watch needs the module as a file and an --out file".into(),
+        });
+    }
+
+    let outputs = outputs(&args)?;
+    let targets: Vec<Target> = outputs.iter().map(|output| output.target).collect();
+
+    // Our own writes are no changes, even when written into a watched directory.
+    let mut watcher = watch::Watcher::new(inputs(&args), written(&args))?;
+
+    let pack = || {
+        let packer = packer(&args)?;
+        write_sidecar(&packer, &args)?;
+        packer.pack_targets(&targets)
+    };
+
+    let mut last = pack()?;
+    for (output, file) in outputs.iter().zip(&last) {
+        write_atomic(output.path(), file)?;
+    }
+
+    loop {
+        watcher.wait()?;
+
+        let files = match pack() {
+            Ok(files) => files,
+            Err(err) => {
+                eprintln!("error: {err}");
+                continue;
+            }
+        };
+
+        // Touching an input without changing it should not make pages reload.
+        for ((output, file), last) in outputs.iter().zip(files).zip(&mut last) {
+            if file != *last {
+                write_atomic(output.path(), &file)?;
+                eprintln!("wrote {}", output.path().display());
+                *last = file;
+            }
+        }
+    }
+}
+
+/// The primary output of `--target` and `--out`, followed by those of `--emit`.
+fn outputs(args: &Args) -> Result<Vec<Output>, PackError> {
+    let primary = Output {
+        target: args.target.unwrap_or_default(),
+        path: args.out.clone(),
+    };
+
+    let outputs: Vec<Output> = std::iter::once(primary)
+        .chain(args.emit.iter().cloned())
+        .collect();
 
-    for section in parser.parse_all(&wasm) {
-        if let Some((id, data_range)) = section.map_err(parse_err)?.as_section() {
-            encoder.section(&wasm_encoder::RawSection {
-                id,
-                data: &wasm[data_range],
+    let targets: Vec<Target> = outputs.iter().map(|output| output.target).collect();
+    check_compress(args.compress, &targets)?;
+
+    for (idx, output) in outputs.iter().enumerate() {
+        let Some(path) = &output.path else { continue };
+        if outputs[..idx]
+            .iter()
+            .any(|other| other.path.as_ref() == Some(path))
+        {
+            return Err(PackError::InvalidArguments {
+                reason: format!("`{}` is written by more than one target", path.display()),
             });
         }
     }
 
-    for extra in &args.extra_section {
-        encoder.section(&wasm_encoder::CustomSection {
-            name: &extra.name,
-            data: &std::fs::read(&extra.from_file)?,
+    Ok(outputs)
+}
+
+/// All files we write, the outputs and the sidecar, along with their temporary files.
+fn written(args: &Args) -> Vec<PathBuf> {
+    args.out
+        .iter()
+        .chain(args.emit.iter().filter_map(|output| output.path.as_ref()))
+        .chain(args.debug_sidecar.as_ref())
+        .flat_map(|path| [path.clone(), temp_path(path)])
+        .collect()
+}
+
+/// Only pages of the `html` target are compressed, the option must not be silently ignored.
+fn check_compress(
+    compress: Option<ModuleCompression>,
+    targets: &[Target],
+) -> Result<(), PackError> {
+    if compress.is_some() && !targets.contains(&Target::Html) {
+        return Err(PackError::InvalidArguments {
+            reason: "`--compress` applies only to the `html` target, \
+                     add `--target html` or `--emit html=FILE`"
+                .into(),
         });
     }
 
+    Ok(())
+}
+
+fn packer(args: &Args) -> Result<Packer, PackError> {
+    // Only optional for the subcommands and with a manifest, which must then provide it.
+    let Some(stage_2) = &args.stage_2 else {
+        return Err(PackError::InvalidArguments {
+            reason: "no stage 2 loader given".into(),
+        });
+    };
+
+    let stage_2 = read(stage_2)?;
+    let wasm = match &args.wasm {
+        None => read_stdin()?,
+        Some(path) => read(path)?,
+    };
+
+    let mut packer = Packer::new(stage_2, wasm)
+        .target(args.target.unwrap_or_default())
+        .encoding(args.encoding)
+        .compress(args.compress.unwrap_or_default())
+        .stage1(args.stage1_variant.clone())
+        .minify(args.minify)
+        .verify(args.verify)
+        .strip_debug(args.strip_debug)
+        .force(args.force);
+
+    for pattern in &args.strip_custom {
+        packer = packer.strip_custom(pattern)?;
+    }
+
+    for pattern in &args.keep_custom {
+        packer = packer.keep_custom(pattern)?;
+    }
+
+    if args.split_debug {
+        packer = packer.debug_info(DebugInfo::Embed);
+    }
+
+    if let Some(path) = &args.debug_sidecar {
+        let url = match &args.debug_url {
+            Some(url) => url.clone(),
+            None => path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or_else(|| PackError::InvalidPath { path: path.clone() })?
+                .to_owned(),
+        };
+
+        packer = packer.debug_info(DebugInfo::Sidecar(url));
+    }
+
+    let source_date = source_date(args.reproducible, args.source_date_epoch);
+    if let Some(secs) = source_date {
+        packer = packer.reproducible(secs);
+    }
+
+    if let Some(stage1) = &args.stage_1 {
+        packer = packer.stage1(Stage1::Custom(read(stage1)?));
+    }
+
+    if args.edit {
+        packer = packer.edit(true);
+    }
+
+    if let Some(index) = &args.index_html {
+        packer = packer.index_html(read(index)?);
+    }
+
+    for extra in &args.extra_section {
+        packer = packer.section(&extra.name, extra.data()?);
+    }
+
     if let Some(zip_file) = &args.zip {
-        let zip_data = std::fs::read(zip_file)?;
+        let zip_data = read(zip_file)?;
         let name = args
             .zip_section_name
             .as_deref()
-            .unwrap_or("wah_polyglot_stage2_data");
+            .unwrap_or(Packer::DEFAULT_ZIP_SECTION);
+
+        packer = packer.trailing_zip_as(name, zip_data);
+    }
+
+    if let Some(dir) = &args.data_dir {
+        let mut data = DataDir::new(dir).compression(args.data_compression);
+        if let Some(secs) = source_date {
+            data = data.source_date(secs);
+        }
+        for pattern in &args.data_exclude {
+            data = data.exclude(pattern)?;
+        }
+        // Nor are our own outputs data, when written into the directory.
+        for path in written(args) {
+            data = data.skip(path);
+        }
+
+        let name = args
+            .zip_section_name
+            .as_deref()
+            .unwrap_or(Packer::DEFAULT_ZIP_SECTION);
+
+        packer = packer.trailing_zip_as(name, data.to_zip()?);
+    }
+
+    Ok(packer)
+}
+
+fn run_repack(mut args: RepackArgs) -> Result<(), PackError> {
+    resolve_sections(&mut args.extra_section, true, args.force)?;
+    let file = read(&args.file)?;
+    let target = match args.target {
+        Some(target) => target,
+        None if file.starts_with(b"\0asm") => Target::WasmPlusHtml,
+        None => Target::Html,
+    };
+    check_compress(args.compress, &[target])?;
+
+    let mut packer = Packer::from_polyglot(&file)?
+        .verify(args.verify)
+        .force(args.force);
+
+    if let Some(secs) = source_date(args.reproducible, args.source_date_epoch) {
+        packer = packer.reproducible(secs);
+    }
+
+    if let Some(target) = args.target {
+        packer = packer.target(target);
+    }
+
+    if let Some(encoding) = args.encoding {
+        packer = packer.encoding(encoding);
+    }
+
+    if let Some(compression) = args.compress {
+        packer = packer.compress(compression);
+    }
+
+    if let Some(stage1) = args.stage1_variant {
+        packer = packer.stage1(stage1);
+    }
+
+    if let Some(stage1) = &args.stage_1 {
+        packer = packer.stage1(Stage1::Custom(read(stage1)?));
+    }
+
+    if args.minify {
+        packer = packer.minify(true);
+    }
 
-        encoder.section(&wasm_encoder::CustomSection {
-            name,
-            data: &zip_data,
+    if let Some(stage_2) = &args.stage_2 {
+        packer = packer.stage2(read(stage_2)?);
+    }
+
+    if let Some(index) = &args.index_html {
+        packer = packer.index_html(read(index)?);
+    }
+
+    for extra in &args.extra_section {
+        packer = packer.replace_section(&extra.name, extra.data()?);
+    }
+
+    if let Some(zip_file) = &args.zip {
+        let zip_data = read(zip_file)?;
+        let name = args
+            .zip_section_name
+            .as_deref()
+            .unwrap_or(Packer::DEFAULT_ZIP_SECTION);
+
+        packer = packer.trailing_zip_as(name, zip_data);
+    }
+
+    if args.strip_debug {
+        packer = packer.strip_debug(true);
+    }
+
+    for pattern in &args.strip_custom {
+        packer = packer.strip_custom(pattern)?;
+    }
+
+    for pattern in &args.keep_custom {
+        packer = packer.keep_custom(pattern)?;
+    }
+
+    report_stripped(&packer)?;
+    let file = packer.pack()?;
+    write_out(&[(args.out.as_deref(), file)], args.compress.is_some())
+}
+
+fn run_serve(mut args: ServeArgs) -> Result<(), PackError> {
+    // Only the page itself is served, nothing is written. Outputs of a manifest are ignored.
+    let unsupported = [
+        ("--out", args.pack.out.is_some()),
+        ("--emit", !args.pack.emit.is_empty()),
+        ("--debug-sidecar", args.pack.debug_sidecar.is_some()),
+        ("--compress", args.pack.compress.is_some()),
+    ];
+
+    if let Some((flag, _)) = unsupported.iter().find(|(_, given)| *given) {
+        return Err(PackError::InvalidArguments {
+            reason: format!("serve does not support `{flag}`"),
         });
     }
 
-    let wasm = match args.target {
-        Target::WasmPlusHtml => encoder.finish(),
-        Target::Html => {
-            use base64::{display::Base64Display, engine::general_purpose};
-            let wasm = encoder.finish();
-            let template = include_str!("stage0-html.html");
-
-            // To include our WebAssembly module as data, we need to massage the data into an HTML
-            // compatible form. In the end, access to it as an ArrayBuffer is required. The pure
-            // `fetch` is sometimes limited by the browser so maybe that's a problem? But it is the
-            // most efficient base64 decoder we have. And it is **correct**.
-            //
-            // <https://stackoverflow.com/questions/21797299/convert-base64-string-to-arraybuffer>
-            // There answers are mostly bad, and confidently incorrect.
-            let wasm = Base64Display::new(&wasm, &general_purpose::STANDARD);
-            let data_uri = format!("data:application/octet-stream;base64,{wasm}");
-            let data_uri_constructor = format!("{data_uri}");
-            let with_data = template.replace("__REPLACE_THIS_WITH_WASM_AS_A_DATA_URI__", &data_uri_constructor);
-
-            // 16 MB is generally okay..
-            let loaded = if data_uri.len().ilog2() < 24 {
-                // Nothing to do..
-                with_data.replace("__REPLACE_THIS_WITH_URI_LOADER__", "await (async function() {
-                    let doc = await fetch(URI_SRC);
-                    return await doc.arrayBuffer();
-                })()")
-            } else if data_uri.len().ilog2() < 31 {
-                // For larger module (scarily large) we need a different strategy that is not yet
-                // implemented here. In particular, Firefox makes a 32MB restriction on the size of
-                // a DataUrl which seems to be enforced when `fetch` is called (which converts it
-                // into an URL object? Not quite sure where the actual restriction is placed).
-                // We do
-                //
-                // FIXME: Evaluate ReadableByteStream for chunk-based yields <https://developer.mozilla.org/en-US/docs/Web/API/ReadableByteStreamController>
-                //
-                with_data.replace("__REPLACE_THIS_WITH_URI_LOADER__", r#"await (async function() {
-                    const b64 = URI_SRC.slice(URI_SRC.indexOf(",")+1);
-                    const buffer = new ArrayBuffer((b64.length / 4) * 3 - b64.match(/=*$/)[0].length);
-                    const IDX_STR = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/="
-                    const view = new Uint8Array(buffer);
-                    console.log(`Loading ${view.length} bytes of Base64 module data`);
-
-                    let i = 0;
-                    let j = 0;
-                    for (; j < b64.length;) {
-                        let a = IDX_STR.indexOf(b64.charAt(j++));
-                        let b = IDX_STR.indexOf(b64.charAt(j++));
-                        let c = IDX_STR.indexOf(b64.charAt(j++));
-                        let d = IDX_STR.indexOf(b64.charAt(j++));
-
-                        view[i++] = (a << 2) | (b >> 4);
-                        if (c < 64) view[i++] = ((b & 0xf) << 4) | (c >> 2);
-                        if (d < 64) view[i++] = ((c & 0x3) << 6) | (d >> 0);
-                    }
-
-                    return view;
-                })()"#)
-            } else {
-                // This is too large for most String implementations..
-                panic!("The `html` target does not support modules larger than 2GB.");
-            };
+    prepare(&mut args.pack)?;
+
+    if args.pack.wasm.is_none() {
+        return Err(PackError::InvalidArguments {
+            reason: "serve needs the module as a file, to watch it".into(),
+        });
+    }
+
+    // The page refetches itself as the module, which only the `wasm` target is.
+    if args
+        .pack
+        .target
+        .is_some_and(|target| target != Target::WasmPlusHtml)
+    {
+        return Err(PackError::InvalidArguments {
+            reason: "serve can only reload the `wasm` target".into(),
+        });
+    }
+
+    let mut watcher = watch::Watcher::new(inputs(&args.pack), vec![])?;
+    let pack = || packer(&args.pack)?.edit(true).pack();
 
-            loaded.into()
+    let mut last = pack()?;
+    let server = serve::Server::bind(args.listen, last.clone())?;
+    eprintln!("serving on http://{}/", server.addr());
+
+    loop {
+        let change = watcher.wait()?;
+
+        // Keep serving the last good file until the inputs are fixed.
+        match pack() {
+            // Touching an input without changing it should not make pages reload.
+            Ok(file) if file == last => {}
+            Ok(file) => {
+                eprintln!("{} changed, reloading", change.name());
+                last = file.clone();
+                server.publish(file, change);
+            }
+            Err(err) => eprintln!("error: {err}"),
         }
-    };
+    }
+}
 
-    match &args.out {
-        None => {
-            let mut stdout = std::io::stdout();
-            stdout.write_all(&wasm)?;
+/// All files the pack reads, with what a page needs to reload when they change.
+fn inputs(args: &Args) -> Vec<watch::Input> {
+    use watch::{Change, Input};
+
+    let module = args
+        .wasm
+        .iter()
+        .chain(args.extra_section.iter().filter_map(ExtraSection::path))
+        .chain(&args.zip)
+        .chain(&args.data_dir)
+        .map(|path| (path, Change::Module));
+    let stage2 = args.stage_2.iter().map(|path| (path, Change::Stage2));
+    let stage1 = args
+        .index_html
+        .iter()
+        .chain(&args.stage_1)
+        .map(|path| (path, Change::Stage1));
+
+    module
+        .chain(stage2)
+        .chain(stage1)
+        .map(|(path, change)| Input {
+            path: path.clone(),
+            change,
+        })
+        .collect()
+}
+
+/// Write the debug information split off by `--debug-sidecar`.
+fn write_sidecar(packer: &Packer, args: &Args) -> Result<(), PackError> {
+    match &args.debug_sidecar {
+        Some(path) => write_atomic(path, &packer.debug_module()?),
+        None => Ok(()),
+    }
+}
+
+fn report_stripped(packer: &Packer) -> Result<(), PackError> {
+    let stripped = packer.stripped()?;
+    for section in &stripped {
+        eprintln!("stripped `{}`, {} bytes", section.name, section.range.len());
+    }
+
+    if stripped.len() > 1 {
+        let total: usize = stripped.iter().map(|section| section.range.len()).sum();
+        eprintln!("stripped {} sections, {total} bytes", stripped.len());
+    }
+
+    Ok(())
+}
+
+fn write_out(files: &[(Option<&Path>, Vec<u8>)], report: bool) -> Result<(), PackError> {
+    if report || files.len() > 1 {
+        for (out, file) in files {
+            // Only pages of the `html` target differ in size from the module.
+            let Cow::Owned(module) = inspect::inner_module(file)? else {
+                continue;
+            };
+
+            let into = match (files.len(), out) {
+                (1, _) => String::new(),
+                (_, None) => " for stdout".into(),
+                (_, Some(path)) => format!(" for {}", path.display()),
+            };
+
+            eprintln!(
+                "module of {} bytes packed into {} bytes ({:.1}%){into}",
+                module.len(),
+                file.len(),
+                file.len() as f64 * 100.0 / module.len() as f64,
+            );
         }
-        Some(path) => {
-            std::fs::write(path, &wasm)?;
+    }
+
+    for (out, file) in files {
+        match out {
+            None => std::io::stdout().lock().write_all(file)?,
+            Some(path) => write_atomic(path, file)?,
         }
     }
 
     Ok(())
 }
 
-fn parse_err(_: wasmparser::BinaryReaderError) -> std::io::Error {
-    todo!()
+fn run_inspect(args: InspectArgs) -> Result<(), PackError> {
+    let file = read(&args.file)?;
+    let wasm = inspect::inner_module(&file)?;
+
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+
+    if wasm.as_ptr() != file.as_ptr() {
+        writeln!(
+            stdout,
+            "html target, module of {} bytes decoded from the page",
+            wasm.len()
+        )?;
+    }
+
+    for (key, value) in inspect::metadata(&wasm)? {
+        writeln!(stdout, "{key}: {value}")?;
+    }
+
+    writeln!(stdout, "{:>10} {:>10} {:>3}  name", "offset", "size", "id")?;
+    for section in inspect::sections(&wasm)? {
+        let zip = if section.is_zip(&wasm) { " (zip)" } else { "" };
+        writeln!(
+            stdout,
+            "{:>#10x} {:>10} {:>3}  {}{zip}",
+            section.range.start,
+            section.range.len(),
+            section.id,
+            section.name,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn run_extract(args: ExtractArgs) -> Result<(), PackError> {
+    let file = read(&args.file)?;
+    let wasm = inspect::inner_module(&file)?;
+    let data = inspect::section(&wasm, &args.section)?;
+
+    match &args.out {
+        None => std::io::stdout().lock().write_all(data)?,
+        Some(path) => std::fs::write(path, data)?,
+    }
+
+    Ok(())
+}
+
+/// Replace the file in one step, so that readers never see it half-written.
+fn write_atomic(path: &Path, data: &[u8]) -> Result<(), PackError> {
+    let temp = temp_path(path);
+    std::fs::write(&temp, data).map_err(|err| PackError::file(&temp, err))?;
+    std::fs::rename(&temp, path)?;
+    Ok(())
+}
+
+/// Where `write_atomic` writes first.
+///
+/// In the same directory, a rename across file systems would be a copy again.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or("out".as_ref()));
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+/// The time to record in reproducible output, if requested by either argument.
+fn source_date(reproducible: bool, source_date_epoch: Option<u64>) -> Option<u64> {
+    /// 1980-01-01, the earliest date of an entry in a zip file.
+    const ZIP_EPOCH: u64 = 315532800;
+
+    match source_date_epoch {
+        Some(secs) => Some(secs),
+        None if reproducible => Some(ZIP_EPOCH),
+        None => None,
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, PackError> {
+    std::fs::read(path).map_err(|err| PackError::file(path, err))
+}
+
+fn read_stdin() -> Result<Vec<u8>, PackError> {
+    let mut data = vec![];
+    std::io::stdin().lock().read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// One wrapping of the module to write, stdout if without a path.
+#[derive(Clone, Debug)]
+struct Output {
+    target: Target,
+    path: Option<PathBuf>,
+}
+
+impl Output {
+    /// The path of an output that is known to be written to a file.
+    fn path(&self) -> &Path {
+        self.path.as_deref().expect("checked to be a file")
+    }
 }
 
 #[derive(Clone, Debug)]
 struct ExtraSection {
     name: String,
-    from_file: PathBuf,
+    source: SectionSource,
+    encoding: SectionEncoding,
+}
+
+/// Where the data of an added section comes from.
+#[derive(Clone, Debug)]
+enum SectionSource {
+    File(PathBuf),
+    Stdin,
+    /// Given on the command line, already decoded.
+    Inline(Vec<u8>),
+}
+
+/// How the data of an added section is written in its source.
+#[derive(Clone, Copy, Debug)]
+enum SectionEncoding {
+    /// The bytes themselves.
+    Raw,
+    /// The bytes themselves, which must be UTF-8.
+    Text,
+    /// The bytes as base64, surrounding whitespace is ignored.
+    Base64,
+}
+
+impl ExtraSection {
+    fn data(&self) -> Result<Vec<u8>, PackError> {
+        let data = match &self.source {
+            SectionSource::File(path) => read(path)?,
+            SectionSource::Stdin => read_stdin()?,
+            SectionSource::Inline(data) => return Ok(data.clone()),
+        };
+
+        self.encoding
+            .decode(data)
+            .map_err(|reason| PackError::InvalidSectionData {
+                name: self.name.clone(),
+                reason,
+            })
+    }
+
+    fn path(&self) -> Option<&PathBuf> {
+        match &self.source {
+            SectionSource::File(path) => Some(path),
+            _ => None,
+        }
+    }
+}
+
+impl SectionEncoding {
+    fn decode(self, data: Vec<u8>) -> Result<Vec<u8>, String> {
+        use base64::Engine as _;
+
+        match self {
+            Self::Raw => Ok(data),
+            Self::Text => match String::from_utf8(data) {
+                Ok(text) => Ok(text.into_bytes()),
+                Err(err) => Err(err.utf8_error().to_string()),
+            },
+            Self::Base64 => base64::engine::general_purpose::STANDARD
+                .decode(data.trim_ascii())
+                .map_err(|err| err.to_string()),
+        }
+    }
 }
 
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    pack: Args,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List all sections of a packed file, with their offsets and sizes.
+    Inspect(InspectArgs),
+    /// Write the data of one section of a packed file.
+    Extract(ExtractArgs),
+    /// Pack an already packed file again, replacing some of its payloads.
+    ///
+    /// All sections of the original module are preserved as they are. Payloads which are not
+    /// given are kept from the packed file.
+    Repack(RepackArgs),
+    /// Pack the inputs and serve the result on a local address, repacking when they change.
+    ///
+    /// The page uses the hot-reloading stage 1 of `--edit` and reloads only as much as needed: a
+    /// new module is run again by the old stage 2, a new stage 2 is imported, and a new page or
+    /// stage 1 reloads the page. Only the `wasm` target can be served, and no files are written.
+    Serve(ServeArgs),
+}
+
+#[derive(clap::Args)]
+struct ServeArgs {
+    /// The address to listen on.
+    #[arg(long, default_value = "127.0.0.1:8000")]
+    listen: SocketAddr,
+    #[command(flatten)]
+    pack: Args,
+}
+
+#[derive(clap::Args)]
+struct InspectArgs {
+    /// The packed file, of either target.
+    file: PathBuf,
+}
+
+#[derive(clap::Args)]
+struct ExtractArgs {
+    /// The packed file, of either target.
+    file: PathBuf,
+    /// The name of the section, such as `wah_polyglot_stage2`.
+    #[arg(short, long)]
+    section: String,
+    /// A file to write the section to, default stdout.
+    #[arg(short, long)]
+    out: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct RepackArgs {
+    /// The packed file, of either target.
+    file: PathBuf,
+    /// A file to write the module to, default stdout. May be the packed file itself.
+    #[arg(short, long)]
+    out: Option<PathBuf>,
+    /// A new stage 2 loader payload.
+    #[arg(long = "stage2")]
+    stage_2: Option<PathBuf>,
+    /// A new HTML page to use when invoking the loader.
+    #[arg(short, long)]
+    index_html: Option<PathBuf>,
+    /// A new zip file to attach, replacing any trailing zip.
+    #[arg(short, long = "trailing-zip", alias = "zip")]
+    zip: Option<PathBuf>,
+    /// Replace a section of the same name, or add it. Takes the same forms as when packing.
+    #[arg(long = "add-section", value_name = "SECTION")]
+    extra_section: Vec<ExtraSection>,
+    /// Add sections even if their names are duplicate or reserved for the packer.
+    #[arg(long)]
+    force: bool,
+
+    /// Drop the debug information of the module: DWARF `.debug_*` sections, the `name` section
+    /// and `sourceMappingURL` or `external_debug_info` references.
+    #[arg(long)]
+    strip_debug: bool,
+    /// Drop the custom sections of the module whose name matches this glob, e.g. `producers`.
+    #[arg(long, value_name = "PATTERN")]
+    strip_custom: Vec<String>,
+    /// Keep the custom sections of the module whose name matches this glob, even if stripped.
+    #[arg(long, value_name = "PATTERN")]
+    keep_custom: Vec<String>,
+    /// A customized section name to use for the new zip section.
+    #[arg(long = "trailing-zip-section")]
+    zip_section_name: Option<String>,
+    /// Change the target, by default the one of the packed file.
+    #[arg(long, short = 't')]
+    target: Option<Target>,
+    /// Change the encoding of the `html` target, by default the one of the packed file.
+    #[arg(long)]
+    encoding: Option<Encoding>,
+    /// Change the compression of the `html` target, by default the one of the packed file.
+    #[arg(long)]
+    compress: Option<ModuleCompression>,
+    /// Replace the stage 1 loader by one of the built-in variants.
+    #[arg(long, conflicts_with = "stage_1")]
+    stage1_variant: Option<Stage1>,
+    /// Replace the stage 1 loader by a JS module of your own.
+    ///
+    /// Its default export is called with the module as an `ArrayBuffer` and as the compiled
+    /// `WebAssembly.Module`, for either target.
+    #[arg(long = "stage1")]
+    stage_1: Option<PathBuf>,
+    /// Strip comments and indentation from the scripts of stage 0 and stage 1.
+    #[arg(long)]
+    minify: bool,
+    /// Check that the output is valid in all its formats before writing it.
+    #[arg(long)]
+    verify: bool,
+    /// Make the output byte-identical for identical inputs.
+    ///
+    /// Timestamps in the trailing zip are replaced and extra sections are ordered by name.
+    /// Implied by setting `SOURCE_DATE_EPOCH`.
+    #[arg(long)]
+    reproducible: bool,
+    /// The time recorded for all entries with `--reproducible`, in seconds since the unix epoch.
+    ///
+    /// Defaults to the earliest time a zip file can represent, 1980-01-01.
+    #[arg(long, env = "SOURCE_DATE_EPOCH")]
+    source_date_epoch: Option<u64>,
+}
+
+#[derive(clap::Args)]
 struct Args {
     // Positional arguments
     /// The stage 2 loader payload, a JS module.
@@ -182,12 +844,17 @@ struct Args {
     /// indicated `index.html`. The stage 1 will call its default export as
     ///
     /// stage2_module.default(Promise.resolve(new Response(wasmblob)))
-    #[arg(name = "STAGE2_JS")]
-    stage_2: PathBuf,
+    #[arg(name = "STAGE2_JS", required_unless_present = "manifest")]
+    stage_2: Option<PathBuf>,
     /// The web assembly module to embed ourselves in, default stdin.
     wasm: Option<PathBuf>,
 
     // Options.
+    /// Read the arguments from a manifest such as `wah.toml`, paths are relative to it.
+    ///
+    /// Arguments given on the command line take precedence over those of the manifest.
+    #[arg(short, long)]
+    manifest: Option<PathBuf>,
     /// A file to write the module to, default stdout.
     #[arg(short, long)]
     out: Option<PathBuf>,
@@ -198,12 +865,63 @@ struct Args {
     /// A zip file to attach.
     ///
     /// This file is added as a final section of the module (so its central archive is within the
-    /// last 512 bytes).
+    /// last 512 bytes). Its offsets are rewritten so that the output is a valid zip archive.
     #[arg(short, long = "trailing-zip", alias = "zip")]
     zip: Option<PathBuf>,
 
-    #[arg(long = "add-section")]
+    /// A directory to attach as the trailing zip, instead of a prebuilt zip file.
+    ///
+    /// Files are added in the order of their paths, so the archive does not depend on the order
+    /// in which the file system lists them.
+    #[arg(long, conflicts_with = "zip")]
+    data_dir: Option<PathBuf>,
+    /// How to store files from the `--data-dir`, `stored` or `deflate`.
+    #[arg(long, default_value = "deflate")]
+    data_compression: Compression,
+    /// Skip paths in the `--data-dir` matching this glob, relative to the directory.
+    #[arg(long)]
+    data_exclude: Vec<String>,
+
+    /// Add a custom section after those of the module, may be given several times.
+    ///
+    /// * `name,file` or `name=@file`, the contents of a file.
+    /// * `name=@-`, read from stdin. Only when the module is given as a path.
+    /// * `name=text:value`, the value itself, for short data such as configuration.
+    ///
+    /// An encoding before the data, as in `name=base64:@file`, says how it is written: `raw`,
+    /// `text` which must be UTF-8, or `base64` which is decoded. Names must be unique and not be
+    /// one of the sections the packer writes itself, unless `--force` is given.
+    #[arg(long = "add-section", value_name = "SECTION")]
     extra_section: Vec<ExtraSection>,
+    /// Add sections even if their names are duplicate or reserved for the packer.
+    #[arg(long)]
+    force: bool,
+
+    /// Drop the debug information of the module: DWARF `.debug_*` sections, the `name` section
+    /// and `sourceMappingURL` or `external_debug_info` references.
+    #[arg(long)]
+    strip_debug: bool,
+    /// Drop the custom sections of the module whose name matches this glob, e.g. `producers`.
+    #[arg(long, value_name = "PATTERN")]
+    strip_custom: Vec<String>,
+    /// Keep the custom sections of the module whose name matches this glob, even if stripped.
+    #[arg(long, value_name = "PATTERN")]
+    keep_custom: Vec<String>,
+
+    /// Move the DWARF `.debug_*` sections into `wah_polyglot_debug`, for devtools to find.
+    ///
+    /// A small hook runs before stage 1 and compiles the module again with an
+    /// `external_debug_info` section, pointing at a blob of the split sections.
+    #[arg(long, conflicts_with = "debug_sidecar")]
+    split_debug: bool,
+    /// Move the DWARF `.debug_*` sections into a file of their own instead.
+    ///
+    /// The page refers to it by its file name, relative to the document.
+    #[arg(long, value_name = "FILE")]
+    debug_sidecar: Option<PathBuf>,
+    /// The URL under which the `--debug-sidecar` is served, instead of its file name.
+    #[arg(long, requires = "debug_sidecar")]
+    debug_url: Option<String>,
 
     /// A customized section name to use for the final zip section.
     ///
@@ -222,37 +940,97 @@ struct Args {
     /// * `html`, which encodes the resulting module as a blob and loads it. This target is
     ///   generally compatible with web browsers but obviously the output file is no longer a
     ///   WebAssembly module itself.
-    #[arg(long, short = 't', alias = "target", default_value = "wasm")]
-    target: Target,
+    ///
+    /// Defaults to `wasm`.
+    #[arg(long, short = 't', alias = "target")]
+    target: Option<Target>,
+    /// Also write the module wrapped for another target, as `TARGET=PATH`.
+    ///
+    /// May be given several times. The module is built once and then wrapped for each target,
+    /// reporting the size of each output.
+    #[arg(long, value_name = "TARGET=PATH")]
+    emit: Vec<Output>,
+
+    /// How the `html` target writes the module into the page.
+    ///
+    /// * `base64`, a third larger than the module.
+    /// * `base85`, printable ASCII that is a quarter larger than the module.
+    /// * `base122`, UTF-8 that is about a seventh larger than the module.
+    #[arg(long, default_value = "base64")]
+    encoding: Encoding,
+
+    /// Compress the module of the `html` target, `gzip` or `deflate`, and report the sizes.
+    ///
+    /// The page decompresses it with the `DecompressionStream` of the browser.
+    #[arg(long)]
+    compress: Option<ModuleCompression>,
+
+    /// Check that the output is valid in all its formats before writing it.
+    ///
+    /// That is, the module validates, the stage 0 header stays inert as HTML, and the central
+    /// directory of a trailing zip can be found from the end of the file.
+    #[arg(long)]
+    verify: bool,
+
+    /// The built-in stage 1 loader, which prepares the page and hands control to stage 2.
+    ///
+    /// * `wasm-bindgen`, stage 2 is the JS glue of wasm-bindgen and gets the module.
+    /// * `instantiate`, instantiate the module without glue. Stage 2 may export its `imports`.
+    /// * `wasi`, run the module as a WASI command in the page. Stage 2 may export `args`, `env`.
+    /// * `worker`, run stage 2 as a module worker which receives the compiled module.
+    #[arg(long, default_value = "wasm-bindgen", conflicts_with = "stage_1")]
+    stage1_variant: Stage1,
+    /// A stage 1 loader of your own, a JS module with the default export `init(bytes, wasm)`.
+    #[arg(long = "stage1")]
+    stage_1: Option<PathBuf>,
 
-    // Experimental section.
-    /// Experimental. Hot-reload when the WASM file changes.
+    /// Strip comments and indentation from the scripts of stage 0 and stage 1.
     ///
-    /// Details and use-case are not entirely fixed. Should we 'reboot' into the new stage0,
-    /// stage1, or stage2? At the moment it calls the _old_ stage2 with the _new_ WASM data. This
-    /// works with Yew Apps, for example.
+    /// This keeps the header of the polyglot small, the scripts still read fine.
+    #[arg(long)]
+    minify: bool,
+
+    /// Make the output byte-identical for identical inputs.
+    ///
+    /// Timestamps in the trailing zip are replaced and extra sections are ordered by name.
+    /// Implied by setting `SOURCE_DATE_EPOCH`.
+    #[arg(long)]
+    reproducible: bool,
+    /// The time recorded for all entries with `--reproducible`, in seconds since the unix epoch.
     ///
-    /// Must set the environment variable `WAH_POLYGLOT_EXPERIMENTAL` to use.
+    /// Defaults to the earliest time a zip file can represent, 1980-01-01.
+    #[arg(long, env = "SOURCE_DATE_EPOCH")]
+    source_date_epoch: Option<u64>,
+
+    /// Keep running, and pack again whenever one of the input files changes.
+    ///
+    /// The output is only replaced when it differs, in one step so that a polling page never
+    /// reads it half-written.
+    #[arg(long)]
+    watch: bool,
+
+    /// Hot-reload when the file changes, using a special stage 1.
+    ///
+    /// Served by `wasm-as-html serve`, the page is told what changed and calls the _old_ stage 2
+    /// with the _new_ WASM data, imports the new stage 2, or reloads. Otherwise it polls its own
+    /// file every second and always calls the old stage 2. This works with Yew Apps, for example.
+    /// Only for the `wasm` target, a page of the `html` target is not the module it refetches.
     #[arg(long, alias = "dev")]
     edit: bool,
 }
 
-#[derive(Clone)]
-enum Target {
-    WasmPlusHtml,
-    Html,
-}
-
-impl core::str::FromStr for Target {
+impl core::str::FromStr for Output {
     type Err = String;
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "wasm" => Ok(Self::WasmPlusHtml),
-            "wasm+html" => Ok(Self::WasmPlusHtml),
-            "html" => Ok(Self::Html),
-            _ => Err(format!("Unknown target selection {s}")),
-        }
+    fn from_str(val: &str) -> Result<Self, Self::Err> {
+        let Some((target, path)) = val.split_once('=') else {
+            return Err("expected `target=file_name`".into());
+        };
+
+        Ok(Output {
+            target: target.parse()?,
+            path: Some(path.into()),
+        })
     }
 }
 
@@ -260,13 +1038,38 @@ impl core::str::FromStr for ExtraSection {
     type Err = String;
 
     fn from_str(val: &str) -> Result<Self, Self::Err> {
-        let Some((key, suffix)) = val.split_once(",") else {
-            return Err("expected `section_name,file_name`".into());
+        const EXPECTED: &str = "expected `name,file`, `name=@file`, `name=@-` or `name=text:value`";
+
+        let (name, value) = match (val.find(','), val.find('=')) {
+            (Some(comma), eq) if eq.is_none_or(|eq| comma < eq) => {
+                return Ok(ExtraSection {
+                    name: val[..comma].into(),
+                    source: SectionSource::File(val[comma + 1..].into()),
+                    encoding: SectionEncoding::Raw,
+                });
+            }
+            (_, Some(eq)) => (&val[..eq], &val[eq + 1..]),
+            (_, None) => return Err(EXPECTED.into()),
+        };
+
+        let (encoding, value) = match value.split_once(':') {
+            Some(("raw", value)) => (SectionEncoding::Raw, value),
+            Some(("text", value)) => (SectionEncoding::Text, value),
+            Some(("base64", value)) => (SectionEncoding::Base64, value),
+            _ if value.starts_with('@') => (SectionEncoding::Raw, value),
+            _ => return Err(EXPECTED.into()),
+        };
+
+        let source = match value.strip_prefix('@') {
+            Some("-") => SectionSource::Stdin,
+            Some(path) => SectionSource::File(path.into()),
+            None => SectionSource::Inline(encoding.decode(value.as_bytes().to_vec())?),
         };
 
         Ok(ExtraSection {
-            name: key.into(),
-            from_file: suffix.into(),
+            name: name.into(),
+            source,
+            encoding,
         })
     }
 }
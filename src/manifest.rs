@@ -0,0 +1,157 @@
+//! The project manifest, `wah.toml`, declaring the arguments of a pack.
+//!
+//! ```toml
+//! stage2 = "wasi-loader/out.js"
+//! wasm = "target/generated/todomvc_bg.wasm"
+//! index-html = "index.html"
+//! stage3 = "target/wasm32-wasi/release/unzip.wasm"
+//! wasm-bindgen = "target/generated/todomvc.js"
+//! data-dir = "assets"
+//! target = "html"
+//! out = "todomvc.html"
+//!
+//! [[section]]
+//! name = "wah_polyglot_wasi_config"
+//! file = "config.bin"
+//!
+//! [[emit]]
+//! target = "wasm"
+//! out = "todomvc.wasm"
+//! ```
+//!
+//! All paths are relative to the manifest. Arguments on the command line take precedence.
+use crate::{Args, ExtraSection, Output, SectionEncoding, SectionSource};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use wasm_as_html::{PackError, Target};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Manifest {
+    /// The stage 2 loader payload, a JS module.
+    pub stage2: Option<PathBuf>,
+    /// The web assembly module to embed ourselves in.
+    pub wasm: Option<PathBuf>,
+    /// The page to show, as `--index-html`.
+    pub index_html: Option<PathBuf>,
+    /// The module of stage 3, added as the section `wah_polyglot_stage3`.
+    pub stage3: Option<PathBuf>,
+    /// The JS glue of wasm-bindgen, added as the section `wah_polyglot_wasm_bindgen`.
+    pub wasm_bindgen: Option<PathBuf>,
+    /// A directory to attach as the trailing zip.
+    pub data_dir: Option<PathBuf>,
+    /// A zip file to attach, instead of a directory.
+    pub trailing_zip: Option<PathBuf>,
+    /// How to wrap the module, `wasm` or `html`.
+    pub target: Option<String>,
+    /// The file to write.
+    pub out: Option<PathBuf>,
+    /// Further custom sections.
+    #[serde(default)]
+    pub section: Vec<SectionEntry>,
+    /// Further targets to write, as `--emit`.
+    #[serde(default)]
+    pub emit: Vec<EmitEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SectionEntry {
+    pub name: String,
+    pub file: PathBuf,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmitEntry {
+    pub target: String,
+    pub out: PathBuf,
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Self, PackError> {
+        let text = std::fs::read_to_string(path).map_err(|err| PackError::file(path, err))?;
+        toml::from_str(&text).map_err(|err| invalid(path, err.to_string()))
+    }
+
+    /// Fill in all arguments not given on the command line.
+    ///
+    /// Sections are added in front of those of the command line, which replace sections of the
+    /// same name.
+    pub fn apply(self, path: &Path, args: &mut Args) -> Result<(), PackError> {
+        let base = path.parent().unwrap_or(Path::new(""));
+        let resolve = |file: PathBuf| base.join(file);
+
+        fill(&mut args.stage_2, self.stage2.map(resolve));
+        fill(&mut args.wasm, self.wasm.map(resolve));
+        fill(&mut args.index_html, self.index_html.map(resolve));
+        fill(&mut args.out, self.out.map(resolve));
+
+        // The command line may pick the other kind of trailing zip.
+        if args.zip.is_none() && args.data_dir.is_none() {
+            args.zip = self.trailing_zip.map(resolve);
+            args.data_dir = self.data_dir.map(resolve);
+        }
+
+        if args.target.is_none() {
+            if let Some(target) = &self.target {
+                let target: Target = target.parse().map_err(|err| invalid(path, err))?;
+                args.target = Some(target);
+            }
+        }
+
+        if args.emit.is_empty() {
+            for entry in self.emit {
+                args.emit.push(Output {
+                    target: entry.target.parse().map_err(|err| invalid(path, err))?,
+                    path: Some(resolve(entry.out)),
+                });
+            }
+        }
+
+        let named = [
+            ("wah_polyglot_stage3", self.stage3),
+            ("wah_polyglot_wasm_bindgen", self.wasm_bindgen),
+        ];
+
+        let sections = named
+            .into_iter()
+            .filter_map(|(name, file)| Some((name.to_owned(), file?)))
+            .chain(
+                self.section
+                    .into_iter()
+                    .map(|entry| (entry.name, entry.file)),
+            )
+            .filter(|(name, _)| !args.extra_section.iter().any(|extra| extra.name == *name))
+            .map(|(name, file)| ExtraSection {
+                name,
+                source: SectionSource::File(resolve(file)),
+                encoding: SectionEncoding::Raw,
+            })
+            .collect::<Vec<_>>();
+
+        args.extra_section.splice(0..0, sections);
+
+        if args.stage_2.is_none() {
+            return Err(invalid(
+                path,
+                "no `stage2` given here or as an argument".into(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+fn fill(arg: &mut Option<PathBuf>, value: Option<PathBuf>) {
+    if arg.is_none() {
+        *arg = value;
+    }
+}
+
+fn invalid(path: &Path, reason: String) -> PackError {
+    PackError::InvalidManifest {
+        path: path.to_owned(),
+        reason,
+    }
+}
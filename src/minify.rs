@@ -0,0 +1,110 @@
+//! A small minifier for the scripts of our stages, so the polyglot header stays small.
+//!
+//! This only removes comments and indentation. It keeps all line breaks, so automatic semicolon
+//! insertion works as before, and it knows about string and template literals but not about
+//! regular expression literals. None of the stages use them.
+
+/// Minify a script.
+pub(crate) fn js(src: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(src.len());
+    let mut idx = 0;
+
+    while idx < src.len() {
+        match src[idx] {
+            quote @ (b'\'' | b'"' | b'`') => {
+                let end = literal_end(src, idx, quote);
+                out.extend_from_slice(&src[idx..end]);
+                idx = end;
+            }
+            b'/' if src.get(idx + 1) == Some(&b'*') => {
+                idx = find(src, idx + 2, b"*/").map_or(src.len(), |end| end + 2);
+                // The comment may have separated two tokens.
+                space(&mut out);
+            }
+            b'/' if src.get(idx + 1) == Some(&b'/') => {
+                idx = find(src, idx + 2, b"\n").unwrap_or(src.len());
+            }
+            b'\n' => {
+                trim_end(&mut out);
+                if !out.is_empty() && !out.ends_with(b"\n") {
+                    out.push(b'\n');
+                }
+                idx += 1;
+            }
+            b' ' | b'\t' | b'\r' => {
+                space(&mut out);
+                idx += 1;
+            }
+            other => {
+                out.push(other);
+                idx += 1;
+            }
+        }
+    }
+
+    while matches!(out.last(), Some(b' ' | b'\n')) {
+        out.pop();
+    }
+
+    out
+}
+
+/// Minify the contents of all `<script>` elements of a document.
+pub(crate) fn html_scripts(src: &[u8]) -> Vec<u8> {
+    const OPEN: &[u8] = b"<script>";
+    const CLOSE: &[u8] = b"</script>";
+
+    let mut out = Vec::with_capacity(src.len());
+    let mut idx = 0;
+
+    while let Some(start) = find(src, idx, OPEN) {
+        let start = start + OPEN.len();
+        let Some(end) = find(src, start, CLOSE) else {
+            break;
+        };
+
+        out.extend_from_slice(&src[idx..start]);
+        out.push(b'\n');
+        out.extend_from_slice(&js(&src[start..end]));
+        out.push(b'\n');
+        idx = end;
+    }
+
+    out.extend_from_slice(&src[idx..]);
+    out
+}
+
+/// Add a single space, unless at the start of a line or after another space.
+fn space(out: &mut Vec<u8>) {
+    if !matches!(out.last(), None | Some(b' ' | b'\n')) {
+        out.push(b' ');
+    }
+}
+
+fn trim_end(out: &mut Vec<u8>) {
+    while out.last() == Some(&b' ') {
+        out.pop();
+    }
+}
+
+/// The index after the literal starting at `start`.
+fn literal_end(src: &[u8], start: usize, quote: u8) -> usize {
+    let mut idx = start + 1;
+
+    while idx < src.len() {
+        match src[idx] {
+            b'\\' => idx += 2,
+            ch if ch == quote => return idx + 1,
+            _ => idx += 1,
+        }
+    }
+
+    src.len()
+}
+
+fn find(src: &[u8], from: usize, needle: &[u8]) -> Option<usize> {
+    src.get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|pos| from + pos)
+}
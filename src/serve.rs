@@ -0,0 +1,176 @@
+//! A small HTTP server for development, which tells the page when to reload.
+//!
+//! The packed file is served at `/` and an event stream at [`EVENTS`] sends a `reload` event
+//! with the kind of [`Change`] whenever a new file is published. This is enough for the stage 1
+//! loader of `--edit`, anything else is answered with 404.
+use crate::watch::Change;
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Condvar, Mutex},
+    time::Duration,
+};
+
+/// The path of the event stream, as requested by `stage1-edit.js`.
+const EVENTS: &str = "/wah_polyglot_events";
+
+/// Send a comment in this interval, to notice pages that went away.
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+pub struct Server {
+    addr: SocketAddr,
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    published: Condvar,
+}
+
+struct State {
+    file: Arc<Vec<u8>>,
+    generation: u64,
+    /// The last generation with each kind of change, so a page that missed several of them is
+    /// told the largest.
+    changed: Vec<(Change, u64)>,
+}
+
+impl Server {
+    /// Listen on the address, serving the file until another one is published.
+    pub fn bind(addr: SocketAddr, file: Vec<u8>) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                file: Arc::new(file),
+                generation: 0,
+                changed: vec![],
+            }),
+            published: Condvar::new(),
+        });
+
+        let server = Server {
+            addr: listener.local_addr()?,
+            shared: shared.clone(),
+        };
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let shared = shared.clone();
+                std::thread::spawn(move || {
+                    // The page may go away at any time, there is nobody to report that to.
+                    let _ = handle(stream, &shared);
+                });
+            }
+        });
+
+        Ok(server)
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Serve a new file and tell all open pages about it.
+    pub fn publish(&self, file: Vec<u8>, change: Change) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.file = Arc::new(file);
+        state.generation += 1;
+
+        let generation = state.generation;
+        match state.changed.iter_mut().find(|(kind, _)| *kind == change) {
+            Some((_, last)) => *last = generation,
+            None => state.changed.push((change, generation)),
+        }
+
+        self.shared.published.notify_all();
+    }
+}
+
+impl State {
+    /// The largest change published after the generation a page has seen.
+    fn change_since(&self, seen: u64) -> Option<Change> {
+        self.changed
+            .iter()
+            .filter(|&&(_, generation)| generation > seen)
+            .map(|&(change, _)| change)
+            .max()
+    }
+}
+
+fn handle(stream: TcpStream, shared: &Shared) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut stream = stream;
+
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+
+    // Skip the headers, we do not need any of them.
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let mut parts = request.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let path = path.split('?').next().unwrap_or(path);
+
+    match (method, path) {
+        ("GET", EVENTS) => events(stream, shared),
+        ("GET" | "HEAD", "/" | "/index.html") => {
+            let file = shared.state.lock().unwrap().file.clone();
+            // Always HTML, the `wasm` target is both and the browser must render it.
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\n\
+                 Content-Type: text/html\r\n\
+                 Content-Length: {}\r\n\
+                 Cache-Control: no-store\r\n\
+                 Connection: close\r\n\r\n",
+                file.len()
+            )?;
+
+            if method == "GET" {
+                stream.write_all(&file)?;
+            }
+
+            Ok(())
+        }
+        _ => stream
+            .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"),
+    }
+}
+
+fn events(mut stream: TcpStream, shared: &Shared) -> io::Result<()> {
+    stream.write_all(
+        b"HTTP/1.1 200 OK\r\n\
+          Content-Type: text/event-stream\r\n\
+          Cache-Control: no-store\r\n\r\n",
+    )?;
+
+    let mut state = shared.state.lock().unwrap();
+    let mut seen = state.generation;
+
+    loop {
+        let message = match state.change_since(seen) {
+            Some(change) => {
+                seen = state.generation;
+                format!("event: reload\ndata: {}\n\n", change.name())
+            }
+            None => {
+                let (next, timeout) = shared.published.wait_timeout(state, KEEP_ALIVE).unwrap();
+                state = next;
+
+                if state.generation != seen || !timeout.timed_out() {
+                    continue;
+                }
+
+                ":\n\n".to_owned()
+            }
+        };
+
+        // Do not hold the lock while writing to a slow page.
+        drop(state);
+        stream.write_all(message.as_bytes())?;
+        state = shared.state.lock().unwrap();
+    }
+}
@@ -0,0 +1,51 @@
+/* Debug hook, runs before the actual stage 1 in `wah_polyglot_stage1_next`.
+
+   The packer moved the DWARF sections of the module out of it, either into
+   `wah_polyglot_debug` or into a separate file named by `wah_polyglot_debug_url`.
+   Devtools only look for them through an `external_debug_info` section, which
+   must be part of the compiled bytes. So we append one, pointing at a blob of
+   the embedded sections or at the file, and compile the module again.
+ */
+function leb128(n) {
+  let out = [];
+  do {
+    let byte = n & 0x7f;
+    n >>>= 7;
+    out.push(n ? byte | 0x80 : byte);
+  } while (n);
+  return out;
+}
+
+function custom_section(name, value) {
+  let encoder = new TextEncoder();
+  name = encoder.encode(name);
+  value = encoder.encode(value);
+  let body = [...leb128(name.length), ...name, ...leb128(value.length), ...value];
+  return new Uint8Array([0, ...leb128(body.length), ...body]);
+}
+
+async function init(bytes, wasm) {
+  let embedded = WebAssembly.Module.customSections(wasm, 'wah_polyglot_debug');
+  let external = WebAssembly.Module.customSections(wasm, 'wah_polyglot_debug_url');
+
+  let url = null;
+  if (embedded.length) {
+    url = URL.createObjectURL(new Blob([embedded[0]], { type: 'application/wasm' }));
+  } else if (external.length) {
+    /* The module has no location of its own, resolve against the document instead. */
+    url = new URL(new TextDecoder().decode(external[0]), document.baseURI).href;
+  }
+
+  if (url) {
+    let debug = new Blob([bytes, custom_section('external_debug_info', url)]);
+    bytes = await debug.arrayBuffer();
+    wasm = await WebAssembly.compileStreaming(new Response(debug, { headers: { 'content-type': 'application/wasm' }}));
+  }
+
+  let next = WebAssembly.Module.customSections(wasm, 'wah_polyglot_stage1_next')[0];
+  let blob = new Blob([next], { type: 'application/javascript' });
+  let stage1 = (await import(URL.createObjectURL(blob)));
+  await stage1.default(bytes, wasm);
+}
+
+export default init;
@@ -1,4 +1,4 @@
-async function init(bytes, wasm, initial) {
+async function init(bytes, wasm) {
   let index_html = WebAssembly.Module.customSections(wasm, 'wah_polyglot_stage1_html');
 
   if (index_html.length) {
@@ -20,9 +20,7 @@ async function init(bytes, wasm, initial) {
        function can take a Promise to a Response object that resolves to the WASM module.
        Since we have it already we just create a synthetic response.
    **/
-  let blob = new Blob([stage2[0]], { type: 'application/javascript' });
-  let blobURL = URL.createObjectURL(blob);
-  let stage2_module = (await import(blobURL));
+  let stage2_module = await import_stage2(stage2[0]);
 
   /** wasm-bindgen: creates one 
   */
@@ -31,8 +29,61 @@ async function init(bytes, wasm, initial) {
     stage2_module.default(Promise.resolve(new Response(wasmblob)));
   }
 
+  async function with_stage2(bytes, wasm) {
+    stage2_module = await import_stage2(WebAssembly.Module.customSections(wasm, 'wah_polyglot_stage2')[0]);
+    with_bytes(bytes);
+  }
+
   with_bytes(bytes);
-  refetch(bytes, with_bytes, 1000);
+
+  if (window.location.protocol.startsWith('http') && window.EventSource) {
+    listen(with_bytes, with_stage2, () => refetch(bytes, with_bytes, 1000));
+  } else {
+    refetch(bytes, with_bytes, 1000);
+  }
+}
+
+async function import_stage2(stage2) {
+  let blob = new Blob([stage2], { type: 'application/javascript' });
+  let blobURL = URL.createObjectURL(blob);
+  return (await import(blobURL));
+}
+
+/* Served by `wasm-as-html serve`, which tells us what changed. A new module is run with the old
+   stage 2, a new stage 2 is imported first, everything else reloads the page. Other servers have
+   no event stream, then we poll instead. */
+function listen(with_bytes, with_stage2, fallback) {
+  let events = new EventSource('/wah_polyglot_events');
+  let opened = false;
+
+  events.onopen = function() {
+    opened = true;
+  };
+
+  events.onerror = function() {
+    /* After the first open, the browser reconnects to a restarted server by itself. */
+    if (!opened) {
+      events.close();
+      fallback();
+    }
+  };
+
+  events.addEventListener('reload', async function(e) {
+    if (e.data != 'module' && e.data != 'stage2') {
+      window.location.reload();
+      return;
+    }
+
+    let doc = await fetch(window.location.href, { cache: 'no-store' });
+    let bytes = await doc.arrayBuffer();
+    console.log('Triggering reload of', e.data);
+
+    if (e.data == 'stage2') {
+      await with_stage2(bytes, await WebAssembly.compile(bytes));
+    } else {
+      with_bytes(bytes);
+    }
+  });
 }
 
 async function refetch(bytes, onchange, interval) {
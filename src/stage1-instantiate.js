@@ -0,0 +1,43 @@
+async function init(bytes, wasm) {
+  let index_html = WebAssembly.Module.customSections(wasm, 'wah_polyglot_stage1_html');
+
+  if (index_html.length) {
+    document.documentElement.innerHTML = (new TextDecoder().decode(index_html[0]));
+  } else {
+    document.getElementById('stage0_error').innerText = '';
+  }
+
+  let stage2 = WebAssembly.Module.customSections(wasm, 'wah_polyglot_stage2');
+  if (stage2.length > 1) {
+    throw 'Found duplicate application data. Please check distribution.';
+  }
+
+  /* This is the flavor without JS glue.
+       The module is instantiated as is. A stage 2 module is optional, it may export `imports`
+       for the instance, an object or a function returning one. Its default export is then
+       called with the instance. Otherwise we call the `_start` or `main` export ourselves.
+   **/
+  let stage2_module = {};
+  if (stage2.length) {
+    let blob = new Blob([stage2[0]], { type: 'application/javascript' });
+    let blobURL = URL.createObjectURL(blob);
+    stage2_module = (await import(blobURL));
+  }
+
+  let imports = stage2_module.imports || {};
+  if (typeof imports === 'function') {
+    imports = await imports(wasm);
+  }
+
+  let instance = await WebAssembly.instantiate(wasm, imports);
+
+  if (typeof stage2_module.default === 'function') {
+    await stage2_module.default(instance, wasm);
+  } else if (instance.exports._start) {
+    instance.exports._start();
+  } else if (instance.exports.main) {
+    instance.exports.main();
+  }
+}
+
+export default init;
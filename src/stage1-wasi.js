@@ -0,0 +1,166 @@
+async function init(bytes, wasm) {
+  let index_html = WebAssembly.Module.customSections(wasm, 'wah_polyglot_stage1_html');
+
+  if (index_html.length) {
+    document.documentElement.innerHTML = (new TextDecoder().decode(index_html[0]));
+  } else {
+    document.getElementById('stage0_error').innerText = '';
+  }
+
+  let stage2 = WebAssembly.Module.customSections(wasm, 'wah_polyglot_stage2');
+  if (stage2.length > 1) {
+    throw 'Found duplicate application data. Please check distribution.';
+  }
+
+  /* This is the direct WASI flavor.
+       The module is a WASI command and runs against a minimal system in the page: no files, an
+       empty stdin, and stdout and stderr appended to the element `wah_wasi_output` (created if
+       the page has none). A stage 2 module is optional, it may export the `args` and `env`
+       arrays of strings for the command.
+   **/
+  let stage2_module = {};
+  if (stage2.length) {
+    let blob = new Blob([stage2[0]], { type: 'application/javascript' });
+    let blobURL = URL.createObjectURL(blob);
+    stage2_module = (await import(blobURL));
+  }
+
+  let output = document.getElementById('wah_wasi_output');
+  if (!output) {
+    output = document.body.appendChild(document.createElement('pre'));
+    output.id = 'wah_wasi_output';
+  }
+
+  let args = stage2_module.args || ['main'];
+  let env = stage2_module.env || [];
+  let wasi = preview1(args, env, function(fd, text) {
+    output.append(text);
+    if (fd == 2) {
+      console.error(text);
+    }
+  });
+
+  let instance = await WebAssembly.instantiate(wasm, { wasi_snapshot_preview1: wasi.imports });
+  wasi.bind(instance);
+
+  try {
+    instance.exports._start();
+  } catch (e) {
+    if (!(e instanceof Exit)) {
+      throw e;
+    }
+
+    console.log(`Exited with status ${e.code}`);
+  }
+}
+
+class Exit {
+  constructor(code) {
+    this.code = code;
+  }
+}
+
+/* The subset of `wasi_snapshot_preview1` a command needs to start, all else fails with ENOSYS. */
+function preview1(args, env, write) {
+  const EBADF = 8, ENOSYS = 52, ESPIPE = 70;
+  const encoder = new TextEncoder();
+  const decoders = { 1: new TextDecoder(), 2: new TextDecoder() };
+
+  let memory;
+  const view = () => new DataView(memory.buffer);
+  const bytes = () => new Uint8Array(memory.buffer);
+
+  function sizes(list, count, size) {
+    let total = list.reduce((n, s) => n + encoder.encode(s).length + 1, 0);
+    view().setUint32(count, list.length, true);
+    view().setUint32(size, total, true);
+    return 0;
+  }
+
+  function strings(list, ptrs, buf) {
+    for (const s of list) {
+      const data = encoder.encode(s + '\0');
+      view().setUint32(ptrs, buf, true);
+      bytes().set(data, buf);
+      ptrs += 4;
+      buf += data.length;
+    }
+    return 0;
+  }
+
+  const imports = {
+    args_sizes_get: (count, size) => sizes(args, count, size),
+    args_get: (ptrs, buf) => strings(args, ptrs, buf),
+    environ_sizes_get: (count, size) => sizes(env, count, size),
+    environ_get: (ptrs, buf) => strings(env, ptrs, buf),
+    fd_write(fd, iovs, len, written) {
+      if (!decoders[fd]) {
+        return EBADF;
+      }
+
+      let total = 0;
+      for (let i = 0; i < len; i++) {
+        const ptr = view().getUint32(iovs + 8 * i, true);
+        const n = view().getUint32(iovs + 8 * i + 4, true);
+        write(fd, decoders[fd].decode(bytes().subarray(ptr, ptr + n), { stream: true }));
+        total += n;
+      }
+
+      view().setUint32(written, total, true);
+      return 0;
+    },
+    fd_read(fd, iovs, len, read) {
+      if (fd != 0) {
+        return EBADF;
+      }
+
+      view().setUint32(read, 0, true);
+      return 0;
+    },
+    fd_fdstat_get(fd, stat) {
+      if (fd > 2) {
+        return EBADF;
+      }
+
+      /* A character device without any rights restrictions. */
+      bytes().fill(0, stat, stat + 24);
+      view().setUint8(stat, 2);
+      view().setBigUint64(stat + 8, 0xffffffffn, true);
+      return 0;
+    },
+    fd_prestat_get: () => EBADF,
+    fd_close: () => 0,
+    fd_seek: () => ESPIPE,
+    clock_res_get(id, res) {
+      view().setBigUint64(res, 1000n, true);
+      return 0;
+    },
+    clock_time_get(id, precision, time) {
+      const now = id == 0
+        ? BigInt(Date.now()) * 1000000n
+        : BigInt(Math.round(performance.now() * 1000000));
+      view().setBigUint64(time, now, true);
+      return 0;
+    },
+    random_get(buf, len) {
+      /* At most 64kB per call. */
+      for (let at = 0; at < len; at += 65536) {
+        crypto.getRandomValues(bytes().subarray(buf + at, buf + Math.min(len, at + 65536)));
+      }
+      return 0;
+    },
+    sched_yield: () => 0,
+    proc_exit(code) {
+      throw new Exit(code);
+    },
+  };
+
+  return {
+    imports: new Proxy(imports, { get: (target, name) => target[name] || (() => ENOSYS) }),
+    bind(instance) {
+      memory = instance.exports.memory;
+    },
+  };
+}
+
+export default init;
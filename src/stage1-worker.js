@@ -0,0 +1,35 @@
+async function init(bytes, wasm) {
+  let index_html = WebAssembly.Module.customSections(wasm, 'wah_polyglot_stage1_html');
+
+  if (index_html.length) {
+    document.documentElement.innerHTML = (new TextDecoder().decode(index_html[0]));
+  } else {
+    document.getElementById('stage0_error').innerText = '';
+  }
+
+  let stage2 = WebAssembly.Module.customSections(wasm, 'wah_polyglot_stage2');
+  if (!stage2.length) {
+    throw 'Found no application data. Please check distribution.';
+  }
+  if (stage2.length > 1) {
+    throw 'Found duplicate application data. Please check distribution.';
+  }
+
+  /* This is the worker flavor.
+       Stage 2 runs as a module worker, off the main thread. Its first message is
+       `{ bytes, wasm }` with the bytes of the file and the compiled module. The worker is
+       available to the page as `wah_polyglot_worker` for further messages.
+   **/
+  let blob = new Blob([stage2[0]], { type: 'application/javascript' });
+  let blobURL = URL.createObjectURL(blob);
+  let worker = new Worker(blobURL, { type: 'module' });
+
+  worker.addEventListener('error', function(e) {
+    console.log(e);
+  });
+
+  globalThis.wah_polyglot_worker = worker;
+  worker.postMessage({ bytes, wasm });
+}
+
+export default init;
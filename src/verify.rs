@@ -0,0 +1,146 @@
+//! Check that a polyglot is still valid in all the formats it claims to be.
+use crate::{inspect, PackError};
+use std::borrow::Cow;
+
+const EOCD_SIGNATURE: &[u8] = b"PK\x05\x06";
+const CENTRAL_SIGNATURE: &[u8] = b"PK\x01\x02";
+const LOCATOR64_SIGNATURE: &[u8] = b"PK\x06\x07";
+/// The end of central directory record without its comment.
+const EOCD_LEN: usize = 22;
+/// The zip64 end of central directory record and its locator, as we write them.
+const ZIP64_END_LEN: usize = 56 + 20;
+
+/// Verify a packed file of either target.
+///
+/// * The module must validate, with the features that browsers commonly ship.
+/// * The header of the stage 0 section must not be interpreted as markup, which constrains the
+///   length of that section.
+/// * If the file ends in a zip section, the central directory of the zip must be found by
+///   searching for its end record from the end of the file, and all its offsets must be relative
+///   to the start of the file. Only for the `wasm` target, the `html` target is not a zip file.
+pub fn verify(file: &[u8]) -> Result<(), PackError> {
+    let wasm = inspect::inner_module(file)?;
+
+    let features = wasmparser::WasmFeatures {
+        relaxed_simd: true,
+        threads: true,
+        tail_call: true,
+        exceptions: true,
+        extended_const: true,
+        ..Default::default()
+    };
+
+    wasmparser::Validator::new_with_features(features)
+        .validate_all(&wasm)
+        .map_err(|err| {
+            broken(
+                "wasm",
+                format!("at offset {:#x}: {}", err.offset(), err.message()),
+            )
+        })?;
+
+    let sections = inspect::sections(&wasm)?;
+    verify_stage0(&wasm, &sections)?;
+
+    if let Cow::Borrowed(_) = wasm {
+        if let Some(last) = sections.last().filter(|section| section.is_zip(&wasm)) {
+            verify_zip(file, last.range.start)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Can this byte appear before the stage 0 HTML without starting markup or a reference?
+pub(crate) const fn is_html_inert(byte: u8) -> bool {
+    !matches!(byte, b'<' | b'&')
+}
+
+/// Are all bytes of the LEB128 encoding of this section length inert?
+pub(crate) const fn is_leb128_inert(mut value: usize) -> bool {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            return is_html_inert(byte);
+        } else if !is_html_inert(byte | 0x80) {
+            return false;
+        }
+    }
+}
+
+fn verify_stage0(wasm: &[u8], sections: &[inspect::SectionInfo]) -> Result<(), PackError> {
+    const HEADER_LEN: usize = 8;
+
+    let Some(stage0) = sections.first().filter(|s| s.name == "wah_polyglot_stage0") else {
+        return Err(broken(
+            "html",
+            "the first section is not `wah_polyglot_stage0`".into(),
+        ));
+    };
+
+    // Everything in front of the HTML is the module header, the section id, the section length
+    // and the encoded name. Only the lengths depend on the packed data.
+    let prefix = &wasm[HEADER_LEN..stage0.range.start];
+    if let Some(pos) = prefix.iter().position(|&b| !is_html_inert(b)) {
+        return Err(broken(
+            "html",
+            format!(
+                "byte {:#04x} at offset {:#x} in the stage 0 header starts markup",
+                prefix[pos],
+                HEADER_LEN + pos
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+fn verify_zip(file: &[u8], zip_start: usize) -> Result<(), PackError> {
+    // The record is followed by a comment of at most 64kB.
+    let search_start = file.len().saturating_sub(EOCD_LEN + usize::from(u16::MAX));
+    let eocd = file[search_start..]
+        .windows(EOCD_SIGNATURE.len())
+        .rposition(|window| window == EOCD_SIGNATURE)
+        .map(|pos| search_start + pos)
+        .filter(|&pos| pos + EOCD_LEN <= file.len())
+        .ok_or_else(|| broken("zip", "no end of central directory record".into()))?;
+
+    let record = &file[eocd..][..EOCD_LEN];
+    let cd_size = u32::from_le_bytes(record[12..16].try_into().unwrap()) as usize;
+    let cd_offset = u32::from_le_bytes(record[16..20].try_into().unwrap());
+
+    let zip64_len = match eocd.checked_sub(20) {
+        Some(locator) if file[locator..].starts_with(LOCATOR64_SIGNATURE) => ZIP64_END_LEN,
+        _ => 0,
+    };
+
+    // Tools locate the directory relative to its end record, to account for data in front of
+    // the archive. It must still be part of our zip section.
+    let cd_start = eocd
+        .checked_sub(cd_size + zip64_len)
+        .filter(|&start| start >= zip_start)
+        .ok_or_else(|| broken("zip", "central directory exceeds the zip section".into()))?;
+
+    if cd_size > 0 && !file[cd_start..].starts_with(CENTRAL_SIGNATURE) {
+        return Err(broken(
+            "zip",
+            "central directory not found before its end record".into(),
+        ));
+    }
+
+    // A zip64 archive has its offset only in the zip64 record, which the above skips over.
+    if cd_offset != u32::MAX && cd_offset as usize != cd_start {
+        return Err(broken(
+            "zip",
+            "offsets are not relative to the start of the file".into(),
+        ));
+    }
+
+    Ok(())
+}
+
+fn broken(format: &'static str, reason: String) -> PackError {
+    PackError::BrokenFormat { format, reason }
+}
@@ -0,0 +1,133 @@
+//! Wait for changes to the input files of a pack.
+use notify::Watcher as _;
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::Duration,
+};
+use wasm_as_html::PackError;
+
+/// How long to wait for further events after a first one, editors touch files several times.
+const SETTLE: Duration = Duration::from_millis(100);
+
+/// What a page has to reload for a changed input, from least to most.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Change {
+    /// Run stage 2 again with the new module.
+    Module,
+    /// Import the new stage 2 and run it.
+    Stage2,
+    /// Reload the page, starting over with stage 0.
+    Stage1,
+}
+
+/// An input file or directory of the pack.
+pub struct Input {
+    pub path: PathBuf,
+    pub change: Change,
+}
+
+pub struct Watcher {
+    inputs: Vec<Input>,
+    /// Files within the inputs whose changes are not reported.
+    ignored: Vec<PathBuf>,
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+    // Stops watching when dropped.
+    _watcher: notify::RecommendedWatcher,
+}
+
+impl Change {
+    pub fn name(self) -> &'static str {
+        match self {
+            Change::Module => "module",
+            Change::Stage2 => "stage2",
+            Change::Stage1 => "stage1",
+        }
+    }
+}
+
+impl Watcher {
+    /// Watch the inputs, except for the `ignored` files such as our outputs.
+    pub fn new(inputs: Vec<Input>, ignored: Vec<PathBuf>) -> Result<Self, PackError> {
+        let (send, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(send).map_err(notify_err)?;
+
+        let mut inputs = inputs;
+        for input in &mut inputs {
+            input.path = std::path::absolute(&input.path)?;
+
+            // Editors often replace files instead of writing them, watch the directory so that we
+            // keep seeing the new file.
+            let (path, mode) = if input.path.is_dir() {
+                (input.path.as_path(), notify::RecursiveMode::Recursive)
+            } else {
+                let parent = input.path.parent().unwrap_or(Path::new("/"));
+                (parent, notify::RecursiveMode::NonRecursive)
+            };
+
+            watcher.watch(path, mode).map_err(notify_err)?;
+        }
+
+        let ignored = ignored
+            .iter()
+            .map(std::path::absolute)
+            .collect::<io::Result<_>>()?;
+
+        Ok(Watcher {
+            inputs,
+            ignored,
+            events,
+            _watcher: watcher,
+        })
+    }
+
+    /// Block until an input changed, and report the largest change of a burst of events.
+    pub fn wait(&mut self) -> Result<Change, PackError> {
+        let mut change = None;
+
+        while change.is_none() {
+            let event = self.events.recv().map_err(|_| disconnected())?;
+            change = self.affected(event.map_err(notify_err)?);
+        }
+
+        loop {
+            match self.events.recv_timeout(SETTLE) {
+                Ok(event) => change = change.max(self.affected(event.map_err(notify_err)?)),
+                Err(mpsc::RecvTimeoutError::Timeout) => break,
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Err(disconnected()),
+            }
+        }
+
+        Ok(change.expect("found a change"))
+    }
+
+    fn affected(&self, event: notify::Event) -> Option<Change> {
+        if event.kind.is_access() {
+            return None;
+        }
+
+        let paths: Vec<&PathBuf> = event
+            .paths
+            .iter()
+            .filter(|path| !self.ignored.contains(path))
+            .collect();
+
+        self.inputs
+            .iter()
+            .filter(|input| paths.iter().any(|path| path.starts_with(&input.path)))
+            .map(|input| input.change)
+            .max()
+    }
+}
+
+fn notify_err(err: notify::Error) -> PackError {
+    match err.kind {
+        notify::ErrorKind::Io(err) => PackError::Io(err),
+        _ => PackError::Io(io::Error::other(err)),
+    }
+}
+
+fn disconnected() -> PackError {
+    PackError::Io(io::Error::other("stopped watching the inputs"))
+}
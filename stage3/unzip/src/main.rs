@@ -8,7 +8,7 @@ pub fn main() -> Result<(), zip::result::ZipError> {
     let mut data = vec![];
     stdin.read_to_end(&mut data)?;
 
-    let wasm_binary = std::fs::read("proc/self/exe")?;
+    let _wasm_binary = std::fs::read("proc/self/exe")?;
 
     let data = std::io::Cursor::new(data);
     let mut archive = ZipArchive::new(data)?;
@@ -19,6 +19,7 @@ pub fn main() -> Result<(), zip::result::ZipError> {
     Ok(())
 }
 
+#[allow(dead_code)]
 enum ProcResult {
     Ok,
     Err(zip::result::ZipError),
@@ -0,0 +1,117 @@
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+use wasm_as_html::inspect;
+
+mod common;
+
+/// Pack the inputs in `dir` with the arguments, writing `out.html` there.
+fn pack(dir: &Path, args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_wasm-as-html"))
+        .current_dir(dir)
+        .args(args)
+        .args(["-o", "out.html", "stage2.js", "module.wasm"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn section(dir: &Path, name: &str) -> Vec<u8> {
+    let file = std::fs::read(dir.join("out.html")).unwrap();
+    inspect::section(&file, name).unwrap().to_vec()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn forms() {
+    let dir = common::temp_dir("add-section-forms");
+    common::write_inputs(&dir);
+    std::fs::write(dir.join("data.bin"), b"\0file").unwrap();
+    std::fs::write(dir.join("data.b64"), b"aGVsbG8=\n").unwrap();
+
+    let args = [
+        "--add-section=comma,data.bin",
+        "--add-section=at=@data.bin",
+        "--add-section=stdin=@-",
+        "--add-section=text=text:{\"a\": 1}",
+        "--add-section=raw=raw:x=y",
+        "--add-section=inline=base64:aGk=",
+        "--add-section=decoded=base64:@data.b64",
+    ];
+
+    let output = pack(&dir, &args, b"from stdin");
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    assert_eq!(section(&dir, "comma"), b"\0file");
+    assert_eq!(section(&dir, "at"), b"\0file");
+    assert_eq!(section(&dir, "stdin"), b"from stdin");
+    assert_eq!(section(&dir, "text"), b"{\"a\": 1}");
+    assert_eq!(section(&dir, "raw"), b"x=y");
+    assert_eq!(section(&dir, "inline"), b"hi");
+    assert_eq!(section(&dir, "decoded"), b"hello");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn invalid_forms() {
+    let dir = common::temp_dir("add-section-invalid");
+    common::write_inputs(&dir);
+
+    for arg in [
+        "--add-section=name",
+        "--add-section=name=value",
+        "--add-section=name=base64:!!",
+        "--add-section=name=text:@missing.txt",
+    ] {
+        let output = pack(&dir, &[arg], b"");
+        assert!(!output.status.success(), "{arg} was accepted");
+    }
+
+    // Read only once, and not when the module is read from there.
+    let output = pack(&dir, &["--add-section=a=@-", "--add-section=b=@-"], b"");
+    assert!(stderr(&output).contains("stdin can be read only once"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reserved_and_duplicate_names() {
+    let dir = common::temp_dir("add-section-names");
+    common::write_inputs(&dir);
+
+    let output = pack(&dir, &["--add-section=wah_polyglot_stage2=text:x"], b"");
+    assert!(stderr(&output).contains("reserved"), "{}", stderr(&output));
+
+    let output = pack(
+        &dir,
+        &["--add-section=a=text:x", "--add-section=a=text:y"],
+        b"",
+    );
+    assert!(
+        stderr(&output).contains("more than once"),
+        "{}",
+        stderr(&output)
+    );
+
+    for args in [
+        &["--force", "--add-section=wah_polyglot_stage2=text:x"][..],
+        &[
+            "--force",
+            "--add-section=a=text:x",
+            "--add-section=a=text:y",
+        ],
+    ] {
+        let output = pack(&dir, args, b"");
+        assert!(output.status.success(), "{}", stderr(&output));
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
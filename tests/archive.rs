@@ -0,0 +1,54 @@
+use std::io::{Cursor, Read, Write};
+
+use wasm_as_html::{PackError, Target};
+
+mod common;
+
+fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut writer = zip::ZipWriter::new(Cursor::new(vec![]));
+    for &(name, data) in files {
+        writer.start_file(name, Default::default()).unwrap();
+        writer.write_all(data).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+#[test]
+fn packed_file_is_a_zip() {
+    let files: &[(&str, &[u8])] = &[
+        ("hello.txt", b"Hello, world!"),
+        ("dir/data.bin", &[0; 1000]),
+    ];
+
+    // Placed behind the module, its offsets counted from the start of the file.
+    let packed = common::packer()
+        .trailing_zip(zip(files))
+        .target(Target::WasmPlusHtml)
+        .verify(true)
+        .pack()
+        .unwrap();
+
+    let mut archive = zip::ZipArchive::new(Cursor::new(packed)).unwrap();
+    assert_eq!(archive.len(), files.len());
+
+    for &(name, data) in files {
+        let mut content = vec![];
+        archive
+            .by_name(name)
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        assert_eq!(content, data);
+    }
+}
+
+#[test]
+fn rejects_short_archives() {
+    for zip in [&b""[..], b"PK\x05\x06", b"PK\x05\x06\0\0\0\0"] {
+        let err = common::packer()
+            .trailing_zip(zip.to_vec())
+            .pack()
+            .unwrap_err();
+        assert!(matches!(err, PackError::InvalidZip { .. }), "{err}");
+    }
+}
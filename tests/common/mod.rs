@@ -0,0 +1,34 @@
+//! Setup shared by the integration tests, each of which uses only part of it.
+#![allow(dead_code)]
+
+use wasm_as_html::Packer;
+
+/// The smallest valid module, only the header.
+pub const MODULE: &[u8] = b"\0asm\x01\0\0\0";
+
+/// A stage 2 loader that does nothing.
+pub const STAGE2: &[u8] = b"export default 0;";
+
+/// Pack the empty module.
+pub fn packer() -> Packer {
+    packer_for(MODULE.to_vec())
+}
+
+/// Pack a module with the stage 2 that does nothing.
+pub fn packer_for(wasm: Vec<u8>) -> Packer {
+    Packer::new(STAGE2.to_vec(), wasm)
+}
+
+/// A fresh directory for the files of one test.
+pub fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("wah-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Write the inputs of a pack into the directory, as `stage2.js` and `module.wasm`.
+pub fn write_inputs(dir: &std::path::Path) {
+    std::fs::write(dir.join("stage2.js"), STAGE2).unwrap();
+    std::fs::write(dir.join("module.wasm"), MODULE).unwrap();
+}
@@ -0,0 +1,65 @@
+use std::io::{Cursor, Read};
+
+use wasm_as_html::data::DataDir;
+
+mod common;
+
+fn entries(zip: Vec<u8>) -> Vec<(String, Vec<u8>)> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(zip)).unwrap();
+    (0..archive.len())
+        .map(|idx| {
+            let mut file = archive.by_index(idx).unwrap();
+            let mut data = vec![];
+            file.read_to_end(&mut data).unwrap();
+            (file.name().to_owned(), data)
+        })
+        .collect()
+}
+
+#[test]
+fn sorted_and_excluded() {
+    let dir = common::temp_dir("data-sorted");
+    std::fs::create_dir(dir.join("a")).unwrap();
+    std::fs::write(dir.join("b.txt"), b"b").unwrap();
+    std::fs::write(dir.join("a").join("x.txt"), b"x").unwrap();
+    std::fs::write(dir.join("a").join("image.psd"), b"psd").unwrap();
+
+    let zip = DataDir::new(&dir)
+        .exclude("*.psd")
+        .unwrap()
+        .to_zip()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(
+        entries(zip),
+        [
+            ("a/".to_owned(), vec![]),
+            ("a/x.txt".to_owned(), b"x".to_vec()),
+            ("b.txt".to_owned(), b"b".to_vec()),
+        ]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks_do_not_loop() {
+    use std::os::unix::fs::symlink;
+
+    let dir = common::temp_dir("data-symlinks");
+    std::fs::write(dir.join("file.txt"), b"data").unwrap();
+    symlink(&dir, dir.join("loop")).unwrap();
+    symlink(dir.join("file.txt"), dir.join("link.txt")).unwrap();
+    symlink(dir.join("missing"), dir.join("dangling")).unwrap();
+
+    let zip = DataDir::new(&dir).to_zip().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(
+        entries(zip),
+        [
+            ("file.txt".to_owned(), b"data".to_vec()),
+            ("link.txt".to_owned(), b"data".to_vec()),
+        ]
+    );
+}
@@ -0,0 +1,145 @@
+use wasm_as_html::{inspect, Encoding, ModuleCompression, Packer, Target};
+
+mod common;
+
+const ENCODINGS: [Encoding; 3] = [Encoding::Base64, Encoding::Base85, Encoding::Base122];
+
+/// Every byte value in every position, then some noise, with lengths covering all remainders.
+fn payload(len: usize) -> Vec<u8> {
+    let mut state = 0x2545_f491_u32;
+    (0..len)
+        .map(|idx| match idx {
+            0..=511 => idx as u8,
+            _ => {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            }
+        })
+        .collect()
+}
+
+fn pack(encoding: Encoding, data: Vec<u8>) -> Vec<u8> {
+    common::packer()
+        .section("payload", data)
+        .target(Target::Html)
+        .encoding(encoding)
+        .verify(true)
+        .pack()
+        .unwrap()
+}
+
+/// The text of all chunk templates of a page.
+fn chunks(page: &[u8]) -> Vec<&[u8]> {
+    const START: &[u8] = br#"<template class="wah_data_chunk">"#;
+    let mut chunks = vec![];
+    let mut rest = page;
+
+    while let Some(pos) = rest.windows(START.len()).position(|w| w == START) {
+        rest = &rest[pos + START.len()..];
+        let end = rest.windows(11).position(|w| w == b"</template>").unwrap();
+        chunks.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+
+    chunks
+}
+
+#[test]
+fn round_trip() {
+    for encoding in ENCODINGS {
+        for len in [0, 1, 2, 3, 4, 5, 6, 7, 8, 511, 512, 513, 4096, 70_001] {
+            let data = payload(len);
+            let page = pack(encoding, data.clone());
+
+            let wasm = inspect::inner_module(&page).unwrap();
+            let section = inspect::section(&wasm, "payload").unwrap();
+            assert_eq!(section, data, "{encoding:?} with {len} bytes");
+
+            let repacked = Packer::from_polyglot(&page).unwrap().pack().unwrap();
+            assert_eq!(repacked, page, "{encoding:?} is kept when repacking");
+        }
+    }
+}
+
+#[test]
+fn chunks_are_inert_in_templates() {
+    for encoding in ENCODINGS {
+        let page = pack(encoding, payload(4 << 20));
+        let chunks = chunks(&page);
+        assert!(chunks.len() > 1, "{encoding:?} is split into chunks");
+
+        for text in chunks {
+            // Markup, references, and characters the parser replaces or normalizes.
+            let bad = text
+                .iter()
+                .position(|b| matches!(b, b'<' | b'&' | b'\0' | b'\r'));
+            assert_eq!(bad, None, "{encoding:?} produced a forbidden byte");
+            assert!(
+                std::str::from_utf8(text).is_ok(),
+                "{encoding:?} is valid UTF-8"
+            );
+        }
+    }
+}
+
+#[test]
+fn compressed_round_trip() {
+    for compression in [ModuleCompression::Gzip, ModuleCompression::Deflate] {
+        let data = payload(70_001);
+        let page = common::packer()
+            .section("payload", data.clone())
+            .target(Target::Html)
+            .compress(compression)
+            .verify(true)
+            .pack()
+            .unwrap();
+
+        let wasm = inspect::inner_module(&page).unwrap();
+        assert_eq!(inspect::section(&wasm, "payload").unwrap(), data);
+
+        let repacked = Packer::from_polyglot(&page).unwrap().pack().unwrap();
+        assert_eq!(repacked, page, "{compression:?} is kept when repacking");
+    }
+}
+
+#[test]
+fn compress_needs_an_html_output() {
+    let dir = common::temp_dir("compress");
+    common::write_inputs(&dir);
+
+    let pack = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_wasm-as-html"))
+            .current_dir(&dir)
+            .args(["--compress", "gzip", "-o", "out.wasm"])
+            .args(args)
+            .args(["stage2.js", "module.wasm"])
+            .output()
+            .unwrap()
+    };
+
+    let wasm_only = pack(&[]);
+    let with_target = pack(&["--target", "html"]);
+    let with_emit = pack(&["--emit", "html=page.html"]);
+    let repack = std::process::Command::new(env!("CARGO_BIN_EXE_wasm-as-html"))
+        .current_dir(&dir)
+        .args(["repack", "--compress", "gzip", "out.wasm"])
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let stderr =
+        |output: &std::process::Output| String::from_utf8_lossy(&output.stderr).into_owned();
+    assert!(!wasm_only.status.success());
+    assert!(stderr(&wasm_only).contains("only to the `html` target"));
+    assert!(stderr(&repack).contains("only to the `html` target"));
+
+    // Only the page is reported, the module is as large as itself.
+    assert!(with_target.status.success(), "{}", stderr(&with_target));
+    assert_eq!(stderr(&with_target).lines().count(), 1);
+    assert!(with_emit.status.success(), "{}", stderr(&with_emit));
+    let report = stderr(&with_emit);
+    assert_eq!(report.lines().count(), 1, "{report}");
+    assert!(report.contains("packed into") && report.ends_with("for page.html\n"));
+}
@@ -0,0 +1,81 @@
+use std::path::Path;
+use std::process::Command;
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use wasm_as_html::{inspect, Target};
+
+mod common;
+
+fn run(args: &[&str], file: &Path) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_wasm-as-html"))
+        .args(args)
+        .arg(file)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{output:?}");
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn inspect_html_target() {
+    let file = common::packer()
+        .section("payload", b"some data".to_vec())
+        .target(Target::Html)
+        .pack()
+        .unwrap();
+    let wasm = inspect::inner_module(&file).unwrap().into_owned();
+
+    let dir = common::temp_dir("inspect-html");
+    let path = dir.join("page.html");
+    std::fs::write(&path, &file).unwrap();
+    let listing = run(&["inspect"], &path);
+    let payload = run(&["extract", "--section", "payload"], &path);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let header = format!("html target, module of {} bytes", wasm.len());
+    assert!(listing.starts_with(&header), "{listing}");
+    for (key, value) in inspect::metadata(&wasm).unwrap() {
+        assert!(
+            listing.contains(&format!("\n{key}: {value}\n")),
+            "{listing}"
+        );
+    }
+
+    for section in inspect::sections(&wasm).unwrap() {
+        let line = format!(
+            "{:>#10x} {:>10} {:>3}  {}",
+            section.range.start,
+            section.range.len(),
+            section.id,
+            section.name
+        );
+        assert!(listing.contains(&line), "{line} missing in {listing}");
+    }
+
+    assert_eq!(payload, "some data");
+}
+
+#[test]
+fn legacy_data_uri() {
+    let wasm = common::packer()
+        .section("payload", b"from a data URI".to_vec())
+        .pack()
+        .unwrap();
+    let page = format!(
+        "<!DOCTYPE html><html><body>\
+         <template id=\"wah_data_uri\">data:application/wasm;base64,{}</template>\
+         </body></html>",
+        STANDARD.encode(&wasm)
+    );
+
+    assert_eq!(inspect::inner_module(page.as_bytes()).unwrap(), wasm);
+
+    let dir = common::temp_dir("inspect-legacy");
+    let path = dir.join("page.html");
+    std::fs::write(&path, &page).unwrap();
+    let payload = run(&["extract", "--section", "payload"], &path);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(payload, "from a data URI");
+}
@@ -0,0 +1,127 @@
+use std::path::Path;
+use std::process::{Command, Output};
+
+use wasm_as_html::inspect;
+
+mod common;
+
+/// Write the manifest and the inputs it names into `project/`, below a fresh directory.
+fn project(name: &str, manifest: &str) -> std::path::PathBuf {
+    let dir = common::temp_dir(name);
+    let project = dir.join("project");
+    std::fs::create_dir(&project).unwrap();
+    common::write_inputs(&project);
+    std::fs::write(project.join("wah.toml"), manifest).unwrap();
+    dir
+}
+
+/// Pack from `dir`, which is not the directory of the manifest.
+fn pack(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_wasm-as-html"))
+        .current_dir(dir)
+        .args(["--manifest", "project/wah.toml"])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn paths_are_relative_to_the_manifest() {
+    let dir = project(
+        "manifest-relative",
+        r#"
+stage2 = "stage2.js"
+wasm = "module.wasm"
+out = "out.html"
+
+[[section]]
+name = "custom"
+file = "custom.bin"
+"#,
+    );
+    std::fs::write(dir.join("project/custom.bin"), b"from the manifest").unwrap();
+
+    let output = pack(&dir, &[]);
+    let file = std::fs::read(dir.join("project/out.html"));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(output.status.success(), "{output:?}");
+    let file = file.unwrap();
+    assert!(file.starts_with(b"\0asm"));
+    assert_eq!(
+        inspect::section(&file, "custom").unwrap(),
+        b"from the manifest"
+    );
+}
+
+#[test]
+fn rejects_unknown_keys() {
+    let dir = project(
+        "manifest-unknown",
+        r#"
+stage2 = "stage2.js"
+wasm = "module.wasm"
+stage-2 = "stage2.js"
+"#,
+    );
+
+    let output = pack(&dir, &[]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("invalid manifest"), "{stderr}");
+    assert!(stderr.contains("stage-2"), "{stderr}");
+}
+
+#[test]
+fn arguments_take_precedence() {
+    let dir = project(
+        "manifest-override",
+        r#"
+stage2 = "stage2.js"
+wasm = "module.wasm"
+target = "html"
+out = "manifest.html"
+
+[[section]]
+name = "custom"
+file = "custom.bin"
+
+[[section]]
+name = "kept"
+file = "custom.bin"
+"#,
+    );
+    std::fs::write(dir.join("project/custom.bin"), b"from the manifest").unwrap();
+
+    let output = pack(
+        &dir,
+        &[
+            "--target",
+            "wasm",
+            "--out",
+            "cli.html",
+            "--add-section=custom=text:from the command line",
+        ],
+    );
+    let file = std::fs::read(dir.join("cli.html"));
+    let manifest_out = dir.join("project/manifest.html").exists();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(output.status.success(), "{output:?}");
+    assert!(!manifest_out, "wrote the output of the manifest");
+    let file = file.unwrap();
+    assert!(
+        file.starts_with(b"\0asm"),
+        "not the target of the command line"
+    );
+    assert_eq!(
+        inspect::section(&file, "custom").unwrap(),
+        b"from the command line"
+    );
+    assert_eq!(
+        inspect::section(&file, "kept").unwrap(),
+        b"from the manifest"
+    );
+}
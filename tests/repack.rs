@@ -0,0 +1,49 @@
+use std::io::{Cursor, Write};
+
+use wasm_as_html::{inspect, Packer};
+
+mod common;
+
+fn zip() -> Vec<u8> {
+    let mut writer = zip::ZipWriter::new(Cursor::new(vec![]));
+    writer.start_file("data.txt", Default::default()).unwrap();
+    writer.write_all(b"Hello, world!").unwrap();
+    writer.finish().unwrap().into_inner()
+}
+
+fn custom_names(file: &[u8]) -> Vec<String> {
+    inspect::sections(file)
+        .unwrap()
+        .into_iter()
+        .filter(|section| section.is_custom())
+        .map(|section| section.name)
+        .collect()
+}
+
+#[test]
+fn keeps_the_trailing_zip() {
+    let file = common::packer()
+        .section("user", b"data".to_vec())
+        .trailing_zip_as("assets", zip())
+        .pack()
+        .unwrap();
+
+    let repacked = Packer::from_polyglot(&file).unwrap().pack().unwrap();
+    assert!(repacked == file, "repacking changed the file");
+    assert_eq!(custom_names(&repacked).last().unwrap(), "assets");
+}
+
+#[test]
+fn user_section_like_a_zip() {
+    let file = common::packer()
+        .section("user", b"PK\x03\x04 but not a zip".to_vec())
+        .pack()
+        .unwrap();
+
+    let repacked = Packer::from_polyglot(&file).unwrap().pack().unwrap();
+    assert!(repacked == file, "repacking changed the file");
+    assert_eq!(
+        inspect::section(&repacked, "user").unwrap(),
+        b"PK\x03\x04 but not a zip"
+    );
+}
@@ -0,0 +1,72 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::{Cursor, Write};
+use std::time::{Duration, SystemTime};
+
+use wasm_as_html::data::DataDir;
+
+mod common;
+
+const SOURCE_DATE: u64 = 1_000_000_000;
+
+fn zip_with_time(year: u16) -> Vec<u8> {
+    let time = zip::DateTime::from_date_and_time(year, 1, 1, 0, 0, 0).unwrap();
+    let options = zip::write::FileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored)
+        .last_modified_time(time);
+
+    let mut writer = zip::ZipWriter::new(Cursor::new(vec![]));
+    writer.start_file("data.txt", options).unwrap();
+    writer.write_all(b"Hello, world!").unwrap();
+    writer.finish().unwrap().into_inner()
+}
+
+fn pack(zip: Vec<u8>, sections: &[&str]) -> u64 {
+    let mut packer = common::packer()
+        .reproducible(SOURCE_DATE)
+        .trailing_zip(zip)
+        .verify(true);
+
+    for &name in sections {
+        packer = packer.section(name, name.as_bytes().to_vec());
+    }
+
+    let mut hasher = DefaultHasher::new();
+    packer.pack().unwrap().hash(&mut hasher);
+    hasher.finish()
+}
+
+#[test]
+fn zip_timestamps_and_section_order() {
+    let first = pack(zip_with_time(1999), &["a", "b"]);
+    let second = pack(zip_with_time(2023), &["b", "a"]);
+    assert_eq!(first, second);
+}
+
+#[test]
+fn data_dir_modification_times() {
+    let dir = common::temp_dir("reproducible");
+    std::fs::create_dir(dir.join("sub")).unwrap();
+    let file = dir.join("sub").join("file.txt");
+    std::fs::write(&file, b"contents").unwrap();
+
+    let pack_at = |modified: SystemTime| {
+        std::fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let zip = DataDir::new(&dir)
+            .source_date(SOURCE_DATE)
+            .to_zip()
+            .unwrap();
+        pack(zip, &[])
+    };
+
+    let first = pack_at(SystemTime::UNIX_EPOCH + Duration::from_secs(1_500_000_000));
+    let second = pack_at(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(first, second);
+}
@@ -0,0 +1,143 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Command, Stdio};
+use std::thread::sleep;
+use std::time::Duration;
+
+use wasm_as_html::{PackError, Target};
+
+mod common;
+
+/// Send a request and return the whole response, the server closes each connection.
+fn get(addr: &str, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "GET {path} HTTP/1.1\r\nHost: {addr}\r\n\r\n").unwrap();
+
+    let mut response = vec![];
+    stream.read_to_end(&mut response).unwrap();
+    String::from_utf8_lossy(&response).into_owned()
+}
+
+/// The next event of the stream, skipping comments used to keep it alive.
+fn next_event(events: &mut BufReader<TcpStream>) -> String {
+    let mut event = String::new();
+    loop {
+        let mut line = String::new();
+        events.read_line(&mut line).unwrap();
+        assert!(!line.is_empty(), "the event stream was closed");
+
+        match line.trim_end() {
+            "" if event.is_empty() => {}
+            "" => return event,
+            line if line.starts_with(':') => {}
+            line => event.push_str(&format!("{line}\n")),
+        }
+    }
+}
+
+#[test]
+fn serves_and_reloads() {
+    let dir = common::temp_dir("serve");
+    common::write_inputs(&dir);
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_wasm-as-html"))
+        .arg("serve")
+        .arg("--listen")
+        .arg("127.0.0.1:0")
+        .arg(dir.join("stage2.js"))
+        .arg(dir.join("module.wasm"))
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // Keep reading from the pipe open, the server reports each reload on it.
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let mut line = String::new();
+    stderr.read_line(&mut line).unwrap();
+    let addr = line
+        .trim_end()
+        .strip_prefix("serving on http://")
+        .and_then(|rest| rest.strip_suffix('/'))
+        .unwrap_or_else(|| panic!("unexpected output {line:?}"))
+        .to_owned();
+
+    let page = get(&addr, "/");
+    let missing = get(&addr, "/missing");
+
+    let mut stream = TcpStream::connect(&addr).unwrap();
+    write!(stream, "GET /wah_polyglot_events HTTP/1.1\r\n\r\n").unwrap();
+    let mut events = BufReader::new(stream);
+    let mut header = String::new();
+    while events.read_line(&mut header).unwrap() > 2 {
+        header.clear();
+    }
+
+    // Writing the same module packs the same file, which is not published.
+    std::fs::write(dir.join("module.wasm"), common::MODULE).unwrap();
+    sleep(Duration::from_secs(1));
+    std::fs::write(dir.join("stage2.js"), b"export default 1;").unwrap();
+    let event = next_event(&mut events);
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(page.starts_with("HTTP/1.1 200 OK\r\n"), "{page}");
+    assert!(page.contains("wah_polyglot_stage2"));
+    assert!(
+        missing.starts_with("HTTP/1.1 404 Not Found\r\n"),
+        "{missing}"
+    );
+    assert_eq!(event, "event: reload\ndata: stage2\n");
+}
+
+#[test]
+fn rejects_html_target() {
+    let output = Command::new(env!("CARGO_BIN_EXE_wasm-as-html"))
+        .arg("serve")
+        .arg("--target")
+        .arg("html")
+        .arg("/nonexistent/stage2.js")
+        .arg("/nonexistent/module.wasm")
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("`wasm` target"));
+}
+
+#[test]
+fn rejects_outputs() {
+    for args in [
+        &["--out", "out.html"][..],
+        &["--emit", "html=page.html"],
+        &["--debug-sidecar", "module.debug.wasm"],
+        &["--compress", "gzip"],
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_wasm-as-html"))
+            .arg("serve")
+            .args(args)
+            .arg("/nonexistent/stage2.js")
+            .arg("/nonexistent/module.wasm")
+            .output()
+            .unwrap();
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!output.status.success());
+        assert!(
+            stderr.contains(&format!("does not support `{}`", args[0])),
+            "{stderr}"
+        );
+    }
+}
+
+#[test]
+fn edit_needs_the_wasm_target() {
+    let packer = common::packer().edit(true);
+
+    assert!(packer.pack().is_ok());
+    assert!(matches!(
+        packer.target(Target::Html).pack(),
+        Err(PackError::EditHtmlTarget)
+    ));
+}
@@ -0,0 +1,38 @@
+use wasm_as_html::{inspect, Target};
+
+mod common;
+
+/// The bytes between the module header and the HTML of stage 0, read as text by the browser.
+fn stage0_prefix(wasm: &[u8]) -> &[u8] {
+    let sections = inspect::sections(wasm).unwrap();
+    let stage0 = &sections[0];
+    assert_eq!(stage0.name, "wah_polyglot_stage0");
+    &wasm[8..stage0.range.start]
+}
+
+#[test]
+fn section_length_is_html_inert() {
+    for target in [Target::WasmPlusHtml, Target::Html] {
+        for minify in [false, true] {
+            let file = common::packer()
+                .target(target)
+                .minify(minify)
+                .pack()
+                .unwrap();
+
+            let wasm = inspect::inner_module(&file).unwrap();
+            let prefix = stage0_prefix(&wasm);
+
+            // The section id, then the LEB128 length up to the first byte without continuation.
+            assert_eq!(prefix[0], 0);
+            let leb_len = prefix[1..].iter().position(|b| b & 0x80 == 0).unwrap() + 1;
+            let leb = &prefix[1..][..leb_len];
+
+            assert!(
+                !leb.iter().any(|b| matches!(b, b'<' | b'&')),
+                "{target:?} (minify: {minify}) encodes its length as {leb:02x?}"
+            );
+            assert!(!prefix.iter().any(|b| matches!(b, b'<' | b'&')));
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use wasm_as_html::{inspect, Packer, Stage1};
+
+mod common;
+
+const CUSTOM: &[u8] = b"export default async function(bytes, wasm) {}";
+
+fn variants() -> [Stage1; 6] {
+    [
+        Stage1::WasmBindgen,
+        Stage1::Instantiate,
+        Stage1::Wasi,
+        Stage1::Worker,
+        Stage1::Edit,
+        Stage1::Custom(CUSTOM.to_vec()),
+    ]
+}
+
+#[test]
+fn metadata_names_the_loader() {
+    for stage1 in variants() {
+        let name = stage1.name();
+        let file = common::packer().stage1(stage1).pack().unwrap();
+        let metadata = inspect::metadata(&file).unwrap();
+        assert_eq!(metadata, [("stage1".to_owned(), name.to_owned())]);
+    }
+
+    let file = common::packer()
+        .stage1(Stage1::Custom(CUSTOM.to_vec()))
+        .pack()
+        .unwrap();
+    assert_eq!(
+        inspect::section(&file, "wah_polyglot_stage1").unwrap(),
+        CUSTOM
+    );
+}
+
+#[test]
+fn repack_keeps_the_loader() {
+    for stage1 in variants() {
+        for minify in [false, true] {
+            let file = common::packer()
+                .stage1(stage1.clone())
+                .minify(minify)
+                .pack()
+                .unwrap();
+
+            let repacked = Packer::from_polyglot(&file).unwrap().pack().unwrap();
+            assert_eq!(repacked, file, "{} minify={minify}", stage1.name());
+        }
+    }
+}
+
+#[test]
+fn names_parse_except_edit() {
+    for stage1 in variants().into_iter().take(4) {
+        assert_eq!(stage1.name().parse::<Stage1>().unwrap(), stage1);
+    }
+
+    assert!("edit".parse::<Stage1>().is_err());
+    assert!("custom".parse::<Stage1>().is_err());
+}
@@ -0,0 +1,123 @@
+use std::process::Command;
+
+use wasm_as_html::{inspect, DebugInfo, Packer};
+
+mod common;
+
+fn module() -> Vec<u8> {
+    let mut module = wasm_encoder::Module::new();
+    for name in ["name", ".debug_info", ".debug_line", "producers", "keep"] {
+        module.section(&wasm_encoder::CustomSection {
+            name,
+            data: name.as_bytes(),
+        });
+    }
+    module.finish()
+}
+
+fn custom_names(packer: &Packer) -> Vec<String> {
+    let file = packer.pack().unwrap();
+    inspect::sections(&file)
+        .unwrap()
+        .into_iter()
+        .map(|section| section.name)
+        .filter(|name| !name.starts_with("wah_polyglot_"))
+        .collect()
+}
+
+#[test]
+fn strip_and_keep() {
+    let packer = common::packer_for(module());
+    assert_eq!(custom_names(&packer).len(), 5);
+
+    let packer = packer
+        .strip_debug(true)
+        .strip_custom("prod*")
+        .unwrap()
+        .keep_custom(".debug_line")
+        .unwrap();
+
+    assert_eq!(custom_names(&packer), [".debug_line", "keep"]);
+
+    let stripped: Vec<_> = packer
+        .stripped()
+        .unwrap()
+        .into_iter()
+        .map(|section| (section.name, section.range.len()))
+        .collect();
+    assert_eq!(
+        stripped,
+        [
+            ("name".to_owned(), 4),
+            (".debug_info".to_owned(), 11),
+            ("producers".to_owned(), 9)
+        ]
+    );
+}
+
+#[test]
+fn split_debug_round_trip() {
+    let packer = common::packer_for(module()).debug_info(DebugInfo::Embed);
+    let file = packer.pack().unwrap();
+
+    assert_eq!(custom_names(&packer), ["name", "producers", "keep"]);
+
+    let debug = inspect::section(&file, "wah_polyglot_debug").unwrap();
+    assert_eq!(debug, packer.debug_module().unwrap());
+    let dwarf: Vec<_> = inspect::sections(debug)
+        .unwrap()
+        .into_iter()
+        .map(|section| section.name)
+        .collect();
+    assert_eq!(dwarf, [".debug_info", ".debug_line"]);
+
+    let repacked = Packer::from_polyglot(&file).unwrap().pack().unwrap();
+    assert_eq!(repacked, file);
+}
+
+#[test]
+fn debug_sidecar() {
+    let dir = common::temp_dir("debug-sidecar");
+    common::write_inputs(&dir);
+    std::fs::write(dir.join("module.wasm"), module()).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_wasm-as-html"))
+        .current_dir(&dir)
+        .args(["--debug-sidecar", "module.debug.wasm", "--out", "page.html"])
+        .args(["stage2.js", "module.wasm"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+
+    let file = std::fs::read(dir.join("page.html")).unwrap();
+    let sidecar = std::fs::read(dir.join("module.debug.wasm")).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let dwarf: Vec<_> = inspect::sections(&sidecar)
+        .unwrap()
+        .into_iter()
+        .map(|section| (section.name.clone(), sidecar[section.range].to_vec()))
+        .collect();
+    assert_eq!(
+        dwarf,
+        [
+            (".debug_info".to_owned(), b".debug_info".to_vec()),
+            (".debug_line".to_owned(), b".debug_line".to_vec()),
+        ]
+    );
+
+    let names: Vec<_> = inspect::sections(&file)
+        .unwrap()
+        .into_iter()
+        .map(|section| section.name)
+        .collect();
+    assert!(!names.iter().any(|name| name.starts_with(".debug_")));
+
+    // The hook runs first and compiles the module with an `external_debug_info` at this URL.
+    let hook = inspect::section(&file, "wah_polyglot_stage1").unwrap();
+    assert!(String::from_utf8_lossy(hook).contains("'external_debug_info'"));
+    assert_eq!(
+        inspect::section(&file, "wah_polyglot_debug_url").unwrap(),
+        b"module.debug.wasm"
+    );
+}
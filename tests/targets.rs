@@ -0,0 +1,23 @@
+use wasm_as_html::{inspect, Packer, Target};
+
+mod common;
+
+fn packer() -> Packer {
+    common::packer()
+        .section("payload", b"some data".to_vec())
+        .verify(true)
+}
+
+#[test]
+fn all_targets_match_single_packs() {
+    let targets = [Target::Html, Target::WasmPlusHtml, Target::Html];
+    let files = packer().pack_targets(&targets).unwrap();
+
+    assert_eq!(files.len(), targets.len());
+    for (&target, file) in targets.iter().zip(&files) {
+        assert_eq!(*file, packer().target(target).pack().unwrap());
+    }
+
+    // Both wrap the very same module.
+    assert_eq!(*inspect::inner_module(&files[0]).unwrap(), files[1]);
+}
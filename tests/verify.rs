@@ -0,0 +1,64 @@
+use wasm_as_html::{verify::verify, PackError, Target};
+
+mod common;
+
+const CHUNK_START: &[u8] = br#"<template class="wah_data_chunk">"#;
+
+fn html_page() -> Vec<u8> {
+    common::packer()
+        .section("payload", vec![0x55; 4096])
+        .target(Target::Html)
+        .pack()
+        .unwrap()
+}
+
+#[test]
+fn accepts_packed_files() {
+    for target in [Target::WasmPlusHtml, Target::Html] {
+        let file = common::packer().target(target).pack().unwrap();
+        verify(&file).unwrap();
+    }
+}
+
+#[test]
+fn rejects_garbage() {
+    let err = verify(b"<!DOCTYPE html><p>Not packed at all</p>").unwrap_err();
+    assert!(matches!(err, PackError::UnknownFormat), "{err}");
+}
+
+#[test]
+fn rejects_truncated_chunk() {
+    let mut page = html_page();
+    let start = page
+        .windows(CHUNK_START.len())
+        .position(|window| window == CHUNK_START)
+        .unwrap()
+        + CHUNK_START.len();
+
+    // Drop a few characters from the middle of the first chunk, keeping its end tag.
+    page.drain(start + 100..start + 108);
+    assert!(verify(&page).is_err());
+
+    // Without its end tag the chunk is not recognized at all.
+    let page = html_page();
+    assert!(verify(&page[..start + 100]).is_err());
+}
+
+#[test]
+fn rejects_truncated_module() {
+    let file = common::packer().pack().unwrap();
+    let err = verify(&file[..file.len() - 1]).unwrap_err();
+    assert!(
+        matches!(err, PackError::BrokenFormat { format: "wasm", .. }),
+        "{err}"
+    );
+}
+
+#[test]
+fn rejects_bare_module() {
+    let err = verify(common::MODULE).unwrap_err();
+    assert!(
+        matches!(err, PackError::BrokenFormat { format: "html", .. }),
+        "{err}"
+    );
+}
@@ -0,0 +1,68 @@
+use std::io::Cursor;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+mod common;
+
+/// Wait until the file exists and differs from `old`.
+fn changed(path: &Path, old: &[u8]) -> Vec<u8> {
+    let start = Instant::now();
+    loop {
+        if let Ok(data) = std::fs::read(path) {
+            if data != old {
+                return data;
+            }
+        }
+
+        assert!(start.elapsed() < Duration::from_secs(10), "no change");
+        sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn output_inside_data_dir() {
+    let dir = common::temp_dir("watch");
+    let data = dir.join("data");
+    std::fs::create_dir(&data).unwrap();
+    std::fs::write(data.join("file.txt"), b"first").unwrap();
+    common::write_inputs(&dir);
+    let out = data.join("out.html");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_wasm-as-html"))
+        .arg("--watch")
+        .arg("--data-dir")
+        .arg(&data)
+        .arg("--out")
+        .arg(&out)
+        .arg(dir.join("stage2.js"))
+        .arg(dir.join("module.wasm"))
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let first = changed(&out, b"");
+
+    // Writing the output is no change of the inputs, it is not packed again.
+    sleep(Duration::from_secs(1));
+    let idle = std::fs::read(&out).unwrap();
+
+    // While a change of the data is.
+    std::fs::write(data.join("file.txt"), b"second").unwrap();
+    let second = changed(&out, &first);
+    sleep(Duration::from_secs(1));
+    let settled = std::fs::read(&out).unwrap();
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(idle == first, "the output was packed again");
+    assert!(settled == second, "the output was packed again");
+
+    // Nor is it packed as data.
+    let archive = zip::ZipArchive::new(Cursor::new(settled)).unwrap();
+    let names: Vec<_> = archive.file_names().collect();
+    assert_eq!(names, ["file.txt"]);
+}
@@ -267,7 +267,7 @@ impl<'st> StringSection<'st> {
         let pre_start: u32 = u32::try_from(byte_stream.len()).expect("Unhandled strings offset, too much data");
         let post_skip = pre_start + 12;
         let post = post_skip + self.offset;
-        let pad = 3 - (post + 3) & 0x3;
+        let pad = (3 - (post + 3)) & 0x3;
         
         byte_stream.extend_from_slice(bytemuck::cast_slice::<u32, u8>(&[INST_SKIP, 1, post+pad]));
         assert_eq!(byte_stream.len(), post_skip as usize);